@@ -3,8 +3,13 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+fn new_instrument_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Stock {
+    #[serde(default = "new_instrument_id")]
     pub id: String,
     pub symbol: String,
     pub currency: String,
@@ -46,6 +51,10 @@ impl Instrument for Stock {
     fn notional(&self) -> f64 {
         self.shares
     }
+
+    fn market_data_key(&self) -> &str {
+        &self.symbol
+    }
     
     fn as_any(&self) -> &dyn std::any::Any {
         self
@@ -54,6 +63,7 @@ impl Instrument for Stock {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Bond {
+    #[serde(default = "new_instrument_id")]
     pub id: String,
     pub isin: String,
     pub currency: String,
@@ -117,6 +127,10 @@ impl Instrument for Bond {
     fn notional(&self) -> f64 {
         self.face_value
     }
+
+    fn market_data_key(&self) -> &str {
+        &self.isin
+    }
     
     fn as_any(&self) -> &dyn std::any::Any {
         self
@@ -125,6 +139,7 @@ impl Instrument for Bond {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FinancialOption {
+    #[serde(default = "new_instrument_id")]
     pub id: String,
     pub underlying: String,
     pub currency: String,
@@ -191,8 +206,35 @@ impl Instrument for FinancialOption {
     fn notional(&self) -> f64 {
         self.quantity
     }
+
+    fn market_data_key(&self) -> &str {
+        &self.underlying
+    }
     
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 }
+
+// Wrapper over the concrete instrument types for instruments supplied inline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum InstrumentDefinition {
+    Stock(Stock),
+    Bond(Bond),
+    Option(FinancialOption),
+}
+
+impl InstrumentDefinition {
+    pub fn as_instrument(&self) -> &dyn Instrument {
+        match self {
+            InstrumentDefinition::Stock(s) => s,
+            InstrumentDefinition::Bond(b) => b,
+            InstrumentDefinition::Option(o) => o,
+        }
+    }
+
+    pub fn market_data_key(&self) -> &str {
+        self.as_instrument().market_data_key()
+    }
+}
@@ -1,10 +1,193 @@
-use crate::{Greeks, Instrument, MarketContext, Result, RiskMetrics, ValuationError, ValuationResult, Valuator};
-use crate::instruments::{FinancialOption, OptionType};
-use chrono::Utc;
+use crate::{Greeks, Instrument, InstrumentType, MarketContext, Result, RiskMetrics, ValuationError, ValuationResult, Valuator};
+use crate::instruments::{Bond, FinancialOption, OptionType, PaymentFrequency};
+use chrono::{DateTime, Months, Utc};
 use rand::prelude::*;
 use rand_distr::StandardNormal;
+use serde::{Deserialize, Serialize};
 use statrs::distribution::{Continuous, ContinuousCDF, Normal};
 
+// Confidence level used for the per-instrument risk metrics reported by the models
+const RISK_METRICS_CONFIDENCE: f64 = 0.95;
+
+// Pricing models selectable by name, e.g. from an API request
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingModelKind {
+    #[default]
+    BlackScholes,
+    MonteCarlo,
+}
+
+impl PricingModelKind {
+    // Monte Carlo prices options without Greeks
+    pub fn has_option_greeks(&self) -> bool {
+        *self == PricingModelKind::BlackScholes
+    }
+}
+
+// Factory to construct pricing models as trait objects. The kind selects the option model;
+// stocks and bonds are always priced off spot and discounted cash flows respectively.
+pub fn make_valuator(kind: PricingModelKind) -> Box<dyn Valuator> {
+    let option_model: Box<dyn Valuator> = match kind {
+        PricingModelKind::BlackScholes => Box::new(BlackScholesModel::new()),
+        PricingModelKind::MonteCarlo => Box::new(MonteCarloModel::new(10_000, 252)),
+    };
+    Box::new(
+        CompositeValuator::new()
+            .with_model(InstrumentType::Stock, Box::new(BlackScholesModel::new()))
+            .with_model(InstrumentType::Option, option_model)
+            .with_model(InstrumentType::Bond, Box::new(DiscountedCashFlowModel::new())),
+    )
+}
+
+// Routes each instrument to the model registered for its instrument type
+pub struct CompositeValuator {
+    models: Vec<(InstrumentType, Box<dyn Valuator>)>,
+}
+
+impl CompositeValuator {
+    pub fn new() -> Self {
+        Self { models: Vec::new() }
+    }
+
+    pub fn with_model(mut self, instrument_type: InstrumentType, model: Box<dyn Valuator>) -> Self {
+        self.models.retain(|(t, _)| *t != instrument_type);
+        self.models.push((instrument_type, model));
+        self
+    }
+
+    fn model_for(&self, instrument: &dyn Instrument) -> Result<&dyn Valuator> {
+        let instrument_type = instrument.instrument_type();
+        self.models
+            .iter()
+            .find(|(t, _)| *t == instrument_type)
+            .map(|(_, model)| model.as_ref())
+            .ok_or_else(|| ValuationError::PricingModel(format!("No model registered for {:?}", instrument_type)))
+    }
+}
+
+impl Default for CompositeValuator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Valuator for CompositeValuator {
+    fn value(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<ValuationResult> {
+        self.model_for(instrument)?.value(instrument, context)
+    }
+
+    fn calculate_greeks(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<Greeks> {
+        self.model_for(instrument)?.calculate_greeks(instrument, context)
+    }
+
+    fn calculate_risk_metrics(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<RiskMetrics> {
+        self.model_for(instrument)?.calculate_risk_metrics(instrument, context)
+    }
+}
+
+// Remaining coupons and principal, each discounted at the zero rate for its payment date
+pub struct DiscountedCashFlowModel;
+
+impl DiscountedCashFlowModel {
+    pub fn new() -> Self {
+        Self
+    }
+
+    // Remaining cash flows per unit of face value as (years from valuation date, amount)
+    fn cash_flows(&self, bond: &Bond, as_of: DateTime<Utc>) -> Vec<(f64, f64)> {
+        let months_per_period = match bond.payment_frequency {
+            PaymentFrequency::Annual => 12,
+            PaymentFrequency::SemiAnnual => 6,
+            PaymentFrequency::Quarterly => 3,
+            PaymentFrequency::Monthly => 1,
+        };
+        let coupon = bond.coupon_rate * months_per_period as f64 / 12.0;
+
+        // Roll back from maturity so stub periods fall at the start
+        let mut flows = Vec::new();
+        let mut payment_date = Some(bond.maturity);
+        while let Some(date) = payment_date.filter(|d| *d > as_of && *d > bond.issue_date) {
+            flows.push((year_fraction(as_of, date), coupon));
+            payment_date = date.checked_sub_months(Months::new(months_per_period));
+        }
+        if let Some(last) = flows.first_mut() {
+            last.1 += 1.0;
+        }
+        flows.reverse();
+        flows
+    }
+
+    fn present_value(&self, flows: &[(f64, f64)], context: &MarketContext, rate_shift: f64) -> f64 {
+        flows
+            .iter()
+            .map(|(t, amount)| amount * (-(context.rate_for(*t) + rate_shift) * t).exp())
+            .sum()
+    }
+
+    fn downcast<'a>(&self, instrument: &'a dyn Instrument) -> Result<&'a Bond> {
+        instrument.as_any().downcast_ref::<Bond>()
+            .ok_or_else(|| ValuationError::PricingModel("Instrument type not supported by discounted cash flow model".to_string()))
+    }
+}
+
+impl Default for DiscountedCashFlowModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Valuator for DiscountedCashFlowModel {
+    fn value(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<ValuationResult> {
+        let bond = self.downcast(instrument)?;
+        let flows = self.cash_flows(bond, context.timestamp);
+        let price = self.present_value(&flows, context, 0.0);
+
+        Ok(ValuationResult {
+            instrument_id: instrument.id().to_string(),
+            value: price * bond.face_value,
+            currency: instrument.currency().to_string(),
+            timestamp: context.timestamp,
+            confidence: 0.99,
+            greeks: None,
+            risk_metrics: None,
+        })
+    }
+
+    fn calculate_greeks(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<Greeks> {
+        let bond = self.downcast(instrument)?;
+        let flows = self.cash_flows(bond, context.timestamp);
+        let bump = 0.0001;
+        let up = self.present_value(&flows, context, bump);
+        let down = self.present_value(&flows, context, -bump);
+        let base = self.present_value(&flows, context, 0.0);
+        let rolled = self.cash_flows(bond, context.timestamp + chrono::Duration::days(1));
+
+        Ok(Greeks {
+            delta: None,
+            gamma: None,
+            theta: Some(self.present_value(&rolled, context, 0.0) - base),
+            vega: None,
+            // Per 1% parallel move in rates, per unit of face value (same convention as option rho)
+            rho: Some((up - down) / (2.0 * bump) / 100.0),
+        })
+    }
+
+    fn calculate_risk_metrics(&self, instrument: &dyn Instrument, _context: &MarketContext) -> Result<RiskMetrics> {
+        self.downcast(instrument)?;
+        Ok(RiskMetrics {
+            var_1d: None,
+            var_10d: None,
+            expected_shortfall: None,
+            volatility: None,
+        })
+    }
+}
+
+fn year_fraction(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_seconds() as f64 / (365.25 * 24.0 * 3600.0)
+}
+
 pub struct BlackScholesModel;
 
 impl BlackScholesModel {
@@ -28,6 +211,16 @@ impl BlackScholesModel {
                 OptionType::Put => Ok((strike - spot).max(0.0)),
             };
         }
+        // Without volatility the underlying only grows at its carry, so the option is worth its
+        // discounted forward intrinsic value
+        if volatility <= 0.0 {
+            let forward = spot * (-dividend_yield * time_to_expiry).exp();
+            let discounted_strike = strike * (-risk_free_rate * time_to_expiry).exp();
+            return match option_type {
+                OptionType::Call => Ok((forward - discounted_strike).max(0.0)),
+                OptionType::Put => Ok((discounted_strike - forward).max(0.0)),
+            };
+        }
 
         let d1 = ((spot / strike).ln() + (risk_free_rate - dividend_yield + 0.5 * volatility.powi(2)) * time_to_expiry)
             / (volatility * time_to_expiry.sqrt());
@@ -68,6 +261,25 @@ impl BlackScholesModel {
                 rho: Some(0.0),
             });
         }
+        // Zero-volatility limit: N(d1) and N(d2) are 1 in the money and 0 out of it, and the
+        // density terms vanish
+        if volatility <= 0.0 {
+            let carry = (-dividend_yield * time_to_expiry).exp();
+            let discount = (-risk_free_rate * time_to_expiry).exp();
+            let sign = match option_type {
+                OptionType::Call => 1.0,
+                OptionType::Put => -1.0,
+            };
+            let in_the_money = sign * (spot * carry - strike * discount) > 0.0;
+            let weight = if in_the_money { sign } else { 0.0 };
+            return Ok(Greeks {
+                delta: Some(weight * carry),
+                gamma: Some(0.0),
+                theta: Some(weight * (dividend_yield * spot * carry - risk_free_rate * strike * discount) / 365.0),
+                vega: Some(0.0),
+                rho: Some(weight * strike * time_to_expiry * discount / 100.0),
+            });
+        }
 
         let d1 = ((spot / strike).ln() + (risk_free_rate - dividend_yield + 0.5 * volatility.powi(2)) * time_to_expiry)
             / (volatility * time_to_expiry.sqrt());
@@ -122,7 +334,7 @@ impl BlackScholesModel {
 
 impl Valuator for BlackScholesModel {
     fn value(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<ValuationResult> {
-        let now = Utc::now();
+        let now = context.timestamp;
         
         match instrument.instrument_type() {
             crate::InstrumentType::Option => {
@@ -134,12 +346,13 @@ impl Valuator for BlackScholesModel {
                     
                     let time_to_expiry = (opt.expiry - now).num_seconds() as f64 / (365.25 * 24.0 * 3600.0);
                     let dividend_yield = context.dividend_yield.unwrap_or(0.0);
+                    let risk_free_rate = context.rate_for(time_to_expiry);
                     
                     let price = self.black_scholes_price(
                         spot,
                         opt.strike,
                         time_to_expiry,
-                        context.risk_free_rate,
+                        risk_free_rate,
                         volatility,
                         &opt.option_type,
                         dividend_yield,
@@ -147,7 +360,7 @@ impl Valuator for BlackScholesModel {
                     
                     let total_value = price * opt.quantity;
                     let greeks = self.calculate_greeks_bs(
-                        spot, opt.strike, time_to_expiry, context.risk_free_rate,
+                        spot, opt.strike, time_to_expiry, risk_free_rate,
                         volatility, &opt.option_type, dividend_yield
                     )?;
                     
@@ -193,17 +406,26 @@ impl Valuator for BlackScholesModel {
                     let volatility = context.volatility.ok_or_else(|| 
                         ValuationError::MarketData("Missing volatility".to_string()))?;
                     
-                    let time_to_expiry = (opt.expiry - Utc::now()).num_seconds() as f64 / (365.25 * 24.0 * 3600.0);
+                    let time_to_expiry = (opt.expiry - context.timestamp).num_seconds() as f64 / (365.25 * 24.0 * 3600.0);
                     let dividend_yield = context.dividend_yield.unwrap_or(0.0);
+                    let risk_free_rate = context.rate_for(time_to_expiry);
                     
                     self.calculate_greeks_bs(
-                        spot, opt.strike, time_to_expiry, context.risk_free_rate,
+                        spot, opt.strike, time_to_expiry, risk_free_rate,
                         volatility, &opt.option_type, dividend_yield
                     )
                 } else {
                     Err(ValuationError::InvalidInstrument("Failed to downcast to FinancialOption".to_string()))
                 }
             }
+            // Per share: the stock moves one-for-one with itself
+            crate::InstrumentType::Stock => Ok(Greeks {
+                delta: Some(1.0),
+                gamma: Some(0.0),
+                theta: Some(0.0),
+                vega: Some(0.0),
+                rho: Some(0.0),
+            }),
             _ => Ok(Greeks {
                 delta: None,
                 gamma: None,
@@ -214,12 +436,32 @@ impl Valuator for BlackScholesModel {
         }
     }
 
-    fn calculate_risk_metrics(&self, _instrument: &dyn Instrument, _context: &MarketContext) -> Result<RiskMetrics> {
+    fn calculate_risk_metrics(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<RiskMetrics> {
+        let spot = context.spot_price.ok_or_else(||
+            ValuationError::MarketData("Missing spot price".to_string()))?;
+        let volatility = context.volatility.ok_or_else(||
+            ValuationError::MarketData("Missing volatility".to_string()))?;
+
+        // Value change of the position for a 100% relative move in the underlying (delta-normal)
+        let exposure = match instrument.instrument_type() {
+            crate::InstrumentType::Stock => spot * instrument.notional(),
+            crate::InstrumentType::Option => {
+                let delta = self.calculate_greeks(instrument, context)?.delta.unwrap_or(0.0);
+                delta * spot * instrument.notional()
+            }
+            _ => return Err(ValuationError::PricingModel("Instrument type not supported by Black-Scholes model".to_string())),
+        };
+
+        let normal = Normal::new(0.0, 1.0).map_err(|e| ValuationError::RiskCalculation(e.to_string()))?;
+        let z = normal.inverse_cdf(RISK_METRICS_CONFIDENCE);
+        let daily_std = exposure.abs() * volatility * (1.0_f64 / 252.0).sqrt();
+        let var_1d = z * daily_std;
+
         Ok(RiskMetrics {
-            var_1d: None,
-            var_10d: None,
-            expected_shortfall: None,
-            volatility: None,
+            var_1d: Some(var_1d),
+            var_10d: Some(var_1d * 10.0_f64.sqrt()),
+            expected_shortfall: Some(daily_std * normal.pdf(z) / (1.0 - RISK_METRICS_CONFIDENCE)),
+            volatility: Some(volatility),
         })
     }
 }
@@ -269,7 +511,7 @@ impl MonteCarloModel {
 
 impl Valuator for MonteCarloModel {
     fn value(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<ValuationResult> {
-        let now = Utc::now();
+        let now = context.timestamp;
         
         match instrument.instrument_type() {
             crate::InstrumentType::Option => {
@@ -281,10 +523,11 @@ impl Valuator for MonteCarloModel {
                     
                     let time_to_expiry = (opt.expiry - now).num_seconds() as f64 / (365.25 * 24.0 * 3600.0);
                     let dividend_yield = context.dividend_yield.unwrap_or(0.0);
+                    let risk_free_rate = context.rate_for(time_to_expiry);
                     
                     let paths = self.simulate_paths(
                         spot,
-                        context.risk_free_rate,
+                        risk_free_rate,
                         volatility,
                         time_to_expiry,
                         dividend_yield,
@@ -302,7 +545,7 @@ impl Valuator for MonteCarloModel {
                         .collect();
                     
                     let average_payoff = payoffs.iter().sum::<f64>() / payoffs.len() as f64;
-                    let discounted_value = average_payoff * (-context.risk_free_rate * time_to_expiry).exp();
+                    let discounted_value = average_payoff * (-risk_free_rate * time_to_expiry).exp();
                     let total_value = discounted_value * opt.quantity;
                     
                     // Calculate confidence interval
@@ -1,4 +1,4 @@
-use crate::Result;
+use crate::{InstrumentDefinition, PricingModelKind, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -23,6 +23,35 @@ pub struct Greeks {
     pub rho: Option<f64>,
 }
 
+impl Greeks {
+    pub fn scaled(&self, factor: f64) -> Greeks {
+        Greeks {
+            delta: self.delta.map(|g| g * factor),
+            gamma: self.gamma.map(|g| g * factor),
+            theta: self.theta.map(|g| g * factor),
+            vega: self.vega.map(|g| g * factor),
+            rho: self.rho.map(|g| g * factor),
+        }
+    }
+
+    // Sums two sets of Greeks; a Greek missing on one side is treated as zero
+    pub fn combine(&self, other: &Greeks) -> Greeks {
+        fn sum(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+            match (a, b) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+            }
+        }
+        Greeks {
+            delta: sum(self.delta, other.delta),
+            gamma: sum(self.gamma, other.gamma),
+            theta: sum(self.theta, other.theta),
+            vega: sum(self.vega, other.vega),
+            rho: sum(self.rho, other.rho),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RiskMetrics {
     pub var_1d: Option<f64>,
@@ -42,6 +71,50 @@ pub struct MarketContext {
     pub timestamp: DateTime<Utc>,
 }
 
+impl MarketContext {
+    // Zero rate for a horizon in years, linearly interpolated on the yield curve (flat beyond its ends)
+    pub fn rate_for(&self, years: f64) -> f64 {
+        let mut points: Vec<(f64, f64)> = self.yield_curve.as_ref()
+            .map(|curve| curve.iter()
+                .filter_map(|(tenor, rate)| tenor_to_years(tenor).map(|t| (t, *rate)))
+                .collect())
+            .unwrap_or_default();
+        if points.is_empty() {
+            return self.risk_free_rate;
+        }
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let (first, last) = (points[0], points[points.len() - 1]);
+        if years <= first.0 {
+            return first.1;
+        }
+        if years >= last.0 {
+            return last.1;
+        }
+        for pair in points.windows(2) {
+            let ((t0, r0), (t1, r1)) = (pair[0], pair[1]);
+            if years <= t1 {
+                return r0 + (r1 - r0) * (years - t0) / (t1 - t0);
+            }
+        }
+        last.1
+    }
+}
+
+// Parses tenors such as "1D", "2W", "3M" or "10Y" into years
+pub fn tenor_to_years(tenor: &str) -> Option<f64> {
+    let tenor = tenor.trim().to_uppercase();
+    let unit = tenor.chars().last()?;
+    let count: f64 = tenor[..tenor.len() - unit.len_utf8()].parse().ok()?;
+    match unit {
+        'D' => Some(count / 365.0),
+        'W' => Some(count * 7.0 / 365.0),
+        'M' => Some(count / 12.0),
+        'Y' => Some(count),
+        _ => None,
+    }
+}
+
 pub trait Valuator: Send + Sync {
     fn value(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<ValuationResult>;
     fn calculate_greeks(&self, instrument: &dyn Instrument, context: &MarketContext) -> Result<Greeks>;
@@ -54,6 +127,11 @@ pub trait Instrument: std::any::Any {
     fn currency(&self) -> &str;
     fn maturity(&self) -> std::option::Option<DateTime<Utc>>;
     fn notional(&self) -> f64;
+
+    // Symbol whose market data drives the instrument's value (e.g. an option's underlying)
+    fn market_data_key(&self) -> &str {
+        self.id()
+    }
     
     fn as_any(&self) -> &dyn std::any::Any;
 }
@@ -70,17 +148,128 @@ pub enum InstrumentType {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValuationRequest {
+    #[serde(default)]
     pub instrument_ids: Vec<String>,
+    #[serde(default)]
+    pub instruments: Vec<InstrumentDefinition>,
+    #[serde(default = "Utc::now")]
     pub valuation_date: DateTime<Utc>,
-    pub market_context: MarketContext,
+    #[serde(default)]
+    pub market_context: Option<MarketContextOverride>,
+    #[serde(default)]
+    pub model: PricingModelKind,
+    #[serde(default)]
     pub include_greeks: bool,
+    #[serde(default)]
     pub include_risk_metrics: bool,
 }
 
+// Partial market context; fields that are set replace those of the context they are applied to
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarketContextOverride {
+    pub risk_free_rate: Option<f64>,
+    pub dividend_yield: Option<f64>,
+    pub volatility: Option<f64>,
+    pub spot_price: Option<f64>,
+    pub forward_curve: Option<HashMap<String, f64>>,
+    pub yield_curve: Option<HashMap<String, f64>>,
+}
+
+impl MarketContextOverride {
+    pub fn apply(&self, context: &MarketContext) -> MarketContext {
+        let mut context = context.clone();
+        if let Some(rate) = self.risk_free_rate {
+            context.risk_free_rate = rate;
+        }
+        if self.dividend_yield.is_some() {
+            context.dividend_yield = self.dividend_yield;
+        }
+        if self.volatility.is_some() {
+            context.volatility = self.volatility;
+        }
+        if self.spot_price.is_some() {
+            context.spot_price = self.spot_price;
+        }
+        if self.forward_curve.is_some() {
+            context.forward_curve = self.forward_curve.clone();
+        }
+        if self.yield_curve.is_some() {
+            context.yield_curve = self.yield_curve.clone();
+        }
+        context
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValuationResponse {
     pub results: Vec<ValuationResult>,
+    pub errors: Vec<ValuationFailure>,
     pub timestamp: DateTime<Utc>,
     pub total_value: f64,
+    pub total_greeks: Option<Greeks>,
     pub currency: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValuationFailure {
+    pub instrument_id: String,
+    pub error: String,
+}
+
+pub fn value_instruments(
+    valuator: &dyn Valuator,
+    items: &[(&dyn Instrument, MarketContext)],
+    include_greeks: bool,
+    include_risk_metrics: bool,
+    currency: &str,
+) -> ValuationResponse {
+    let mut results = Vec::with_capacity(items.len());
+    let mut errors = Vec::new();
+    let mut total_value = 0.0;
+    let mut total_greeks: Option<Greeks> = None;
+
+    for (instrument, context) in items {
+        let valued = valuator.value(*instrument, context).and_then(|mut result| {
+            result.greeks = if include_greeks {
+                Some(match result.greeks.take() {
+                    Some(greeks) => greeks,
+                    None => valuator.calculate_greeks(*instrument, context)?,
+                })
+            } else {
+                None
+            };
+            if include_risk_metrics {
+                result.risk_metrics = Some(valuator.calculate_risk_metrics(*instrument, context)?);
+            }
+            Ok(result)
+        });
+
+        match valued {
+            Ok(result) => {
+                total_value += result.value;
+                if let Some(greeks) = &result.greeks {
+                    // Greeks are per unit; scale to the position before aggregating
+                    let scaled = greeks.scaled(instrument.notional());
+                    total_greeks = Some(match total_greeks {
+                        Some(total) => total.combine(&scaled),
+                        None => scaled,
+                    });
+                }
+                results.push(result);
+            }
+            Err(e) => errors.push(ValuationFailure {
+                instrument_id: instrument.id().to_string(),
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    ValuationResponse {
+        results,
+        errors,
+        timestamp: Utc::now(),
+        total_value,
+        total_greeks,
+        currency: currency.to_string(),
+    }
+}
@@ -0,0 +1,309 @@
+use crate::{Benchmark, DailyCloses, Instrument, LedgerTrade, Result, Stock, ValuationError};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub const UNCLASSIFIED_SEGMENT: &str = "Unclassified";
+
+// How positions are grouped into attribution segments
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "by", content = "category")]
+pub enum AttributionGrouping {
+    #[default]
+    Sector,
+    InstrumentType,
+    // Value of a user tag category, e.g. "style" or "region"
+    Tag(String),
+}
+
+// User-maintained labels of an instrument
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstrumentClassification {
+    pub sector: Option<String>,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
+
+impl AttributionGrouping {
+    // Parses "sector", "type" (or "instrument_type") and "tag:<category>"
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        match value.to_lowercase().as_str() {
+            "sector" => Ok(Self::Sector),
+            "type" | "instrument_type" => Ok(Self::InstrumentType),
+            _ => match value.split_once(':') {
+                Some((kind, category)) if kind.eq_ignore_ascii_case("tag") && !category.trim().is_empty() => {
+                    Ok(Self::Tag(category.trim().to_string()))
+                }
+                _ => Err(ValuationError::Configuration(format!("Unknown attribution grouping: {}", value))),
+            },
+        }
+    }
+
+    pub fn segment_of(&self, instrument: &dyn Instrument, classification: Option<&InstrumentClassification>) -> String {
+        let segment = match self {
+            Self::Sector => instrument
+                .as_any()
+                .downcast_ref::<Stock>()
+                .and_then(|s| s.sector.clone())
+                .or_else(|| classification.and_then(|c| c.sector.clone())),
+            Self::InstrumentType => Some(format!("{:?}", instrument.instrument_type())),
+            Self::Tag(category) => classification.and_then(|c| c.tags.get(category).cloned()),
+        };
+        segment.filter(|s| !s.trim().is_empty()).unwrap_or_else(|| UNCLASSIFIED_SEGMENT.to_string())
+    }
+}
+
+// Weight and return of one segment over a period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentPerformance {
+    pub segment: String,
+    pub weight: f64,
+    pub return_rate: f64,
+}
+
+// Value-weighted segments from (segment, starting value or weight, return) holdings
+pub fn segment_performance<I>(holdings: I) -> Vec<SegmentPerformance>
+where
+    I: IntoIterator<Item = (String, f64, f64)>,
+{
+    let mut totals: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+    for (segment, value, return_rate) in holdings {
+        if value == 0.0 || !value.is_finite() {
+            continue;
+        }
+        let entry = totals.entry(segment).or_default();
+        entry.0 += value;
+        entry.1 += value * return_rate;
+    }
+    let total: f64 = totals.values().map(|(v, _)| v).sum();
+    if total == 0.0 {
+        return Vec::new();
+    }
+    totals
+        .into_iter()
+        .map(|(segment, (value, weighted))| SegmentPerformance {
+            segment,
+            weight: value / total,
+            return_rate: if value != 0.0 { weighted / value } else { 0.0 },
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SegmentAttribution {
+    pub segment: String,
+    pub portfolio_weight: f64,
+    pub benchmark_weight: f64,
+    pub portfolio_return: f64,
+    pub benchmark_return: f64,
+    // (w_p - w_b) * (R_b,i - R_b)
+    pub allocation: f64,
+    // w_b * (R_p,i - R_b,i)
+    pub selection: f64,
+    // (w_p - w_b) * (R_p,i - R_b,i)
+    pub interaction: f64,
+    pub total: f64,
+}
+
+// Brinson-Fachler attribution of one period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodAttribution {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub portfolio_return: f64,
+    pub benchmark_return: f64,
+    pub excess_return: f64,
+    pub allocation: f64,
+    pub selection: f64,
+    pub interaction: f64,
+    pub segments: Vec<SegmentAttribution>,
+}
+
+// Single-period Brinson-Fachler attribution
+pub fn brinson_fachler(
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    portfolio: &[SegmentPerformance],
+    benchmark: &[SegmentPerformance],
+) -> Result<PeriodAttribution> {
+    if benchmark.iter().map(|s| s.weight).sum::<f64>() <= 0.0 {
+        return Err(ValuationError::Portfolio("Benchmark has no weight to attribute against".to_string()));
+    }
+    let portfolio_return: f64 = portfolio.iter().map(|s| s.weight * s.return_rate).sum();
+    let benchmark_return: f64 = benchmark.iter().map(|s| s.weight * s.return_rate).sum();
+
+    let find = |side: &[SegmentPerformance], segment: &str| side.iter().find(|s| s.segment == segment).cloned();
+    let names: BTreeSet<&str> = portfolio.iter().chain(benchmark).map(|s| s.segment.as_str()).collect();
+    let segments: Vec<SegmentAttribution> = names
+        .into_iter()
+        .map(|name| {
+            let p = find(portfolio, name);
+            let b = find(benchmark, name);
+            let (wp, wb) = (p.as_ref().map_or(0.0, |s| s.weight), b.as_ref().map_or(0.0, |s| s.weight));
+            let rb = b.as_ref().map_or(benchmark_return, |s| s.return_rate);
+            let rp = p.as_ref().map_or(rb, |s| s.return_rate);
+            let allocation = (wp - wb) * (rb - benchmark_return);
+            let selection = wb * (rp - rb);
+            let interaction = (wp - wb) * (rp - rb);
+            SegmentAttribution {
+                segment: name.to_string(),
+                portfolio_weight: wp,
+                benchmark_weight: wb,
+                portfolio_return: rp,
+                benchmark_return: rb,
+                allocation,
+                selection,
+                interaction,
+                total: allocation + selection + interaction,
+            }
+        })
+        .collect();
+
+    Ok(PeriodAttribution {
+        start_date,
+        end_date,
+        portfolio_return,
+        benchmark_return,
+        excess_return: portfolio_return - benchmark_return,
+        allocation: segments.iter().map(|s| s.allocation).sum(),
+        selection: segments.iter().map(|s| s.selection).sum(),
+        interaction: segments.iter().map(|s| s.interaction).sum(),
+        segments,
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkedSegmentAttribution {
+    pub segment: String,
+    pub allocation: f64,
+    pub selection: f64,
+    pub interaction: f64,
+    pub total: f64,
+}
+
+// Periods linked with Carino's logarithmic smoothing, so the effects add up to the compounded excess return
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinkedAttribution {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub portfolio_return: f64,
+    pub benchmark_return: f64,
+    pub excess_return: f64,
+    pub allocation: f64,
+    pub selection: f64,
+    pub interaction: f64,
+    pub segments: Vec<LinkedSegmentAttribution>,
+    pub periods: Vec<PeriodAttribution>,
+}
+
+// Links consecutive period attributions (in date order) with Carino coefficients
+pub fn link_attribution(periods: Vec<PeriodAttribution>) -> Result<LinkedAttribution> {
+    let (Some(first), Some(last)) = (periods.first(), periods.last()) else {
+        return Err(ValuationError::Portfolio("No attribution periods to link".to_string()));
+    };
+    let (start_date, end_date) = (first.start_date, last.end_date);
+    let compound = |r: &mut dyn Iterator<Item = f64>| r.fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0;
+    let portfolio_return = compound(&mut periods.iter().map(|p| p.portfolio_return));
+    let benchmark_return = compound(&mut periods.iter().map(|p| p.benchmark_return));
+    let total_k = carino_coefficient(portfolio_return, benchmark_return);
+
+    let mut segments: BTreeMap<String, LinkedSegmentAttribution> = BTreeMap::new();
+    for period in &periods {
+        let scale = carino_coefficient(period.portfolio_return, period.benchmark_return) / total_k;
+        for s in &period.segments {
+            let linked = segments.entry(s.segment.clone()).or_insert_with(|| LinkedSegmentAttribution {
+                segment: s.segment.clone(),
+                allocation: 0.0,
+                selection: 0.0,
+                interaction: 0.0,
+                total: 0.0,
+            });
+            linked.allocation += s.allocation * scale;
+            linked.selection += s.selection * scale;
+            linked.interaction += s.interaction * scale;
+            linked.total += s.total * scale;
+        }
+    }
+    let segments: Vec<LinkedSegmentAttribution> = segments.into_values().collect();
+
+    Ok(LinkedAttribution {
+        start_date,
+        end_date,
+        portfolio_return,
+        benchmark_return,
+        excess_return: portfolio_return - benchmark_return,
+        allocation: segments.iter().map(|s| s.allocation).sum(),
+        selection: segments.iter().map(|s| s.selection).sum(),
+        interaction: segments.iter().map(|s| s.interaction).sum(),
+        segments,
+        periods,
+    })
+}
+
+// (ln(1 + R_p) - ln(1 + R_b)) / (R_p - R_b), or its limit 1 / (1 + R) when the returns are equal
+fn carino_coefficient(portfolio_return: f64, benchmark_return: f64) -> f64 {
+    let difference = portfolio_return - benchmark_return;
+    if difference.abs() < 1e-12 {
+        1.0 / (1.0 + portfolio_return)
+    } else {
+        ((1.0 + portfolio_return).ln() - (1.0 + benchmark_return).ln()) / difference
+    }
+}
+
+// Daily holdings-based attribution periods over (`from`, `to`]
+pub fn daily_attribution_periods(
+    trades: &[LedgerTrade],
+    closes: &DailyCloses,
+    segments: &HashMap<String, String>,
+    benchmark: &Benchmark,
+    from: Option<NaiveDate>,
+    to: NaiveDate,
+) -> Result<Vec<PeriodAttribution>> {
+    let benchmark_series: Option<Vec<_>> = benchmark.components.iter().map(|c| closes.get(&c.symbol)).collect();
+    let Some(benchmark_series) = benchmark_series else {
+        return Ok(Vec::new());
+    };
+    let mut calendar: BTreeSet<NaiveDate> = benchmark_series[0].keys().copied().filter(|d| *d <= to).collect();
+    for series in &benchmark_series[1..] {
+        calendar.retain(|d| series.contains_key(d));
+    }
+    let calendar: Vec<NaiveDate> = calendar.into_iter().collect();
+
+    let mut sorted: Vec<&LedgerTrade> = trades.iter().collect();
+    sorted.sort_by_key(|t| t.date);
+    let mut pending = sorted.into_iter().peekable();
+    let mut holdings: HashMap<&str, f64> = HashMap::new();
+    let segment_of = |symbol: &str| segments.get(symbol).cloned().unwrap_or_else(|| UNCLASSIFIED_SEGMENT.to_string());
+    let close_on = |symbol: &str, date: NaiveDate| {
+        closes.get(symbol).and_then(|s| s.range(..=date).next_back().map(|(_, p)| *p)).filter(|p| *p > 0.0)
+    };
+
+    let mut periods = Vec::new();
+    for pair in calendar.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        while let Some(trade) = pending.next_if(|t| t.date <= start) {
+            let held = holdings.entry(trade.symbol.as_str()).or_default();
+            *held = (*held + trade.quantity).max(0.0);
+        }
+        if from.is_some_and(|f| start < f) {
+            continue;
+        }
+
+        let portfolio = segment_performance(holdings.iter().filter(|(_, q)| **q > 0.0).filter_map(|(symbol, quantity)| {
+            let (p0, p1) = (close_on(symbol, start)?, close_on(symbol, end)?);
+            Some((segment_of(symbol), quantity * p0, p1 / p0 - 1.0))
+        }));
+        if portfolio.is_empty() {
+            continue;
+        }
+        if benchmark_series.iter().any(|s| s[&start] <= 0.0) {
+            continue;
+        }
+        let benchmark_segments = segment_performance(benchmark.components.iter().zip(&benchmark_series).map(|(c, s)| {
+            (segment_of(&c.symbol), c.weight, s[&end] / s[&start] - 1.0)
+        }));
+        periods.push(brinson_fachler(start, end, &portfolio, &benchmark_segments)?);
+    }
+    Ok(periods)
+}
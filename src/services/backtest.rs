@@ -0,0 +1,157 @@
+use crate::{Result, ValuationError};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{Binomial, ChiSquared, ContinuousCDF, DiscreteCDF};
+
+// VaR predicted at the start of a day (a positive loss) and the P&L realized over the horizon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VarBacktestObservation {
+    pub date: NaiveDate,
+    pub var: f64,
+    pub pnl: f64,
+}
+
+impl VarBacktestObservation {
+    pub fn is_exception(&self) -> bool {
+        self.pnl < -self.var
+    }
+}
+
+// Basel traffic-light zone, based on the cumulative binomial probability of the exception count
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrafficLight {
+    Green,
+    Yellow,
+    Red,
+}
+
+// Likelihood-ratio test result; `rejected` is evaluated at the backtest's significance level
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CoverageTest {
+    pub statistic: f64,
+    pub p_value: f64,
+    pub degrees_of_freedom: u32,
+    pub rejected: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VarBacktestResult {
+    pub observations: usize,
+    pub exceptions: usize,
+    pub expected_exceptions: f64,
+    pub exception_rate: f64,
+    pub confidence_level: f64,
+    pub significance: f64,
+    // Kupiec proportion-of-failures test of unconditional coverage
+    pub kupiec: CoverageTest,
+    // Christoffersen test that exceptions do not cluster
+    pub independence: CoverageTest,
+    // Christoffersen joint test of coverage and independence
+    pub conditional_coverage: CoverageTest,
+    pub traffic_light: TrafficLight,
+    pub exception_dates: Vec<NaiveDate>,
+}
+
+pub fn backtest_var(
+    observations: &[VarBacktestObservation],
+    confidence_level: f64,
+    significance: f64,
+) -> Result<VarBacktestResult> {
+    if observations.is_empty() {
+        return Err(ValuationError::RiskCalculation("No VaR observations to backtest".to_string()));
+    }
+    let in_unit_interval = |x: f64| x > 0.0 && x < 1.0;
+    if !in_unit_interval(confidence_level) || !in_unit_interval(significance) {
+        return Err(ValuationError::RiskCalculation(
+            "Confidence level and significance must be in (0, 1)".to_string(),
+        ));
+    }
+
+    let mut sorted = observations.to_vec();
+    sorted.sort_by_key(|o| o.date);
+    let hits: Vec<bool> = sorted.iter().map(VarBacktestObservation::is_exception).collect();
+
+    let n = hits.len();
+    let exceptions = hits.iter().filter(|h| **h).count();
+    let p = 1.0 - confidence_level;
+
+    // Kupiec POF: H0 exception probability equals 1 - confidence
+    let observed_rate = exceptions as f64 / n as f64;
+    let lr_pof = -2.0
+        * (bernoulli_log_likelihood(n - exceptions, exceptions, p)
+            - bernoulli_log_likelihood(n - exceptions, exceptions, observed_rate));
+
+    // Christoffersen independence: first-order Markov chain of exception indicators
+    let (mut n00, mut n01, mut n10, mut n11) = (0, 0, 0, 0);
+    for pair in hits.windows(2) {
+        match (pair[0], pair[1]) {
+            (false, false) => n00 += 1,
+            (false, true) => n01 += 1,
+            (true, false) => n10 += 1,
+            (true, true) => n11 += 1,
+        }
+    }
+    let pi0 = ratio(n01, n00 + n01);
+    let pi1 = ratio(n11, n10 + n11);
+    let pi = ratio(n01 + n11, n00 + n01 + n10 + n11);
+    let lr_ind = -2.0
+        * (bernoulli_log_likelihood(n00 + n10, n01 + n11, pi)
+            - bernoulli_log_likelihood(n00, n01, pi0)
+            - bernoulli_log_likelihood(n10, n11, pi1));
+
+    let kupiec = chi_squared_test(lr_pof, 1, significance)?;
+    let independence = chi_squared_test(lr_ind, 1, significance)?;
+    let conditional_coverage = chi_squared_test(lr_pof + lr_ind, 2, significance)?;
+
+    Ok(VarBacktestResult {
+        observations: n,
+        exceptions,
+        expected_exceptions: p * n as f64,
+        exception_rate: observed_rate,
+        confidence_level,
+        significance,
+        kupiec,
+        independence,
+        conditional_coverage,
+        traffic_light: traffic_light(exceptions, n, confidence_level)?,
+        exception_dates: sorted.iter().filter(|o| o.is_exception()).map(|o| o.date).collect(),
+    })
+}
+
+// Green below a 95% binomial probability of at most `exceptions`, red from 99.99% (0-4 / 5-9 / 10+ for 250 days at 99%)
+pub fn traffic_light(exceptions: usize, observations: usize, confidence_level: f64) -> Result<TrafficLight> {
+    let binomial = Binomial::new(1.0 - confidence_level, observations as u64)
+        .map_err(|e| ValuationError::RiskCalculation(e.to_string()))?;
+    let cumulative = binomial.cdf(exceptions as u64);
+    Ok(if cumulative < 0.95 {
+        TrafficLight::Green
+    } else if cumulative < 0.9999 {
+        TrafficLight::Yellow
+    } else {
+        TrafficLight::Red
+    })
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator > 0 { numerator as f64 / denominator as f64 } else { 0.0 }
+}
+
+// Log-likelihood of `failures` zeros and `successes` ones with success probability `p`, using 0 * ln(0) = 0
+fn bernoulli_log_likelihood(failures: usize, successes: usize, p: f64) -> f64 {
+    let term = |count: usize, probability: f64| if count == 0 { 0.0 } else { count as f64 * probability.ln() };
+    term(failures, 1.0 - p) + term(successes, p)
+}
+
+fn chi_squared_test(statistic: f64, degrees_of_freedom: u32, significance: f64) -> Result<CoverageTest> {
+    let distribution = ChiSquared::new(degrees_of_freedom as f64)
+        .map_err(|e| ValuationError::RiskCalculation(e.to_string()))?;
+    let statistic = statistic.max(0.0);
+    let p_value = 1.0 - distribution.cdf(statistic);
+    Ok(CoverageTest {
+        statistic,
+        p_value,
+        degrees_of_freedom,
+        rejected: p_value < significance,
+    })
+}
@@ -0,0 +1,241 @@
+use crate::{DailyCloses, Result, ValuationError};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BenchmarkComponent {
+    pub symbol: String,
+    pub weight: f64,
+}
+
+// A single symbol or a weighted basket rebalanced daily to its weights
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Benchmark {
+    pub components: Vec<BenchmarkComponent>,
+}
+
+impl Benchmark {
+    pub fn symbol(symbol: &str) -> Self {
+        Self {
+            components: vec![BenchmarkComponent { symbol: symbol.trim().to_uppercase(), weight: 1.0 }],
+        }
+    }
+
+    // Builds a basket with weights normalized to sum to one
+    pub fn basket(components: Vec<BenchmarkComponent>) -> Result<Self> {
+        let mut merged: Vec<BenchmarkComponent> = Vec::new();
+        for component in components {
+            let symbol = component.symbol.trim().to_uppercase();
+            if symbol.is_empty() {
+                return Err(ValuationError::Configuration("Benchmark symbol must not be empty".to_string()));
+            }
+            if !component.weight.is_finite() || component.weight <= 0.0 {
+                return Err(ValuationError::Configuration(format!("Invalid benchmark weight for {}", symbol)));
+            }
+            match merged.iter_mut().find(|c| c.symbol == symbol) {
+                Some(existing) => existing.weight += component.weight,
+                None => merged.push(BenchmarkComponent { symbol, weight: component.weight }),
+            }
+        }
+        let total: f64 = merged.iter().map(|c| c.weight).sum();
+        if merged.is_empty() || total <= 0.0 {
+            return Err(ValuationError::Configuration("Benchmark needs at least one component".to_string()));
+        }
+        for component in &mut merged {
+            component.weight /= total;
+        }
+        Ok(Self { components: merged })
+    }
+
+    // Parses "SPY" or a basket like "SPY:0.6,AGG:0.4" (weights are normalized)
+    pub fn parse(spec: &str) -> Result<Self> {
+        let components = spec
+            .split(',')
+            .map(|part| {
+                let (symbol, weight) = match part.split_once(':') {
+                    Some((symbol, weight)) => (symbol, weight.trim().parse::<f64>().map_err(|_| {
+                        ValuationError::Configuration(format!("Invalid benchmark weight: {}", weight.trim()))
+                    })?),
+                    None => (part, 1.0),
+                };
+                Ok(BenchmarkComponent { symbol: symbol.to_string(), weight })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::basket(components)
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        self.components.iter().map(|c| c.symbol.clone()).collect()
+    }
+
+    // Returns on dates where every component has a close on the date and the previous common date
+    pub fn daily_returns(&self, closes: &DailyCloses) -> Vec<(NaiveDate, f64)> {
+        let series: Option<Vec<_>> = self.components.iter().map(|c| closes.get(&c.symbol)).collect();
+        let Some(series) = series else {
+            return Vec::new();
+        };
+        let mut common: BTreeSet<NaiveDate> = series[0].keys().copied().collect();
+        for s in &series[1..] {
+            common.retain(|d| s.contains_key(d));
+        }
+
+        let dates: Vec<NaiveDate> = common.into_iter().collect();
+        dates
+            .windows(2)
+            .filter_map(|pair| {
+                let mut total = 0.0;
+                for (component, s) in self.components.iter().zip(&series) {
+                    let (previous, current) = (s[&pair[0]], s[&pair[1]]);
+                    if previous <= 0.0 {
+                        return None;
+                    }
+                    total += component.weight * (current / previous - 1.0);
+                }
+                Some((pair[1], total))
+            })
+            .collect()
+    }
+}
+
+// Portfolio returns measured against a benchmark over `[start_date, end_date]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkStatistics {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub observations: usize,
+    pub portfolio_return: f64,
+    pub benchmark_return: f64,
+    pub excess_return: f64,
+    pub beta: Option<f64>,
+    // Jensen's alpha: mean excess return not explained by beta to the benchmark
+    pub alpha: Option<f64>,
+    pub correlation: Option<f64>,
+    pub r_squared: Option<f64>,
+    pub tracking_error: Option<f64>,
+    pub information_ratio: Option<f64>,
+    // Ratio of geometric mean returns on days the benchmark rose
+    pub up_capture: Option<f64>,
+    // Ratio of geometric mean returns on days the benchmark fell
+    pub down_capture: Option<f64>,
+}
+
+pub struct BenchmarkAnalyzer {
+    risk_free_rate: f64,
+}
+
+impl BenchmarkAnalyzer {
+    pub fn new(risk_free_rate: f64) -> Self {
+        Self { risk_free_rate }
+    }
+
+    // Statistics over the dates present in both series
+    pub fn analyze(&self, portfolio: &[(NaiveDate, f64)], benchmark: &[(NaiveDate, f64)]) -> Result<BenchmarkStatistics> {
+        let aligned = align_returns(portfolio, benchmark);
+        if aligned.is_empty() {
+            return Err(ValuationError::RiskCalculation(
+                "No overlapping portfolio and benchmark returns".to_string(),
+            ));
+        }
+        Ok(self.statistics(&aligned))
+    }
+
+    // Statistics over each trailing `window` of aligned returns, one entry per end date
+    pub fn rolling(
+        &self,
+        portfolio: &[(NaiveDate, f64)],
+        benchmark: &[(NaiveDate, f64)],
+        window: usize,
+    ) -> Result<Vec<BenchmarkStatistics>> {
+        if window < 2 {
+            return Err(ValuationError::RiskCalculation("Rolling window must be at least 2 days".to_string()));
+        }
+        let aligned = align_returns(portfolio, benchmark);
+        Ok(aligned.windows(window).map(|w| self.statistics(w)).collect())
+    }
+
+    fn statistics(&self, aligned: &[(NaiveDate, f64, f64)]) -> BenchmarkStatistics {
+        let n = aligned.len();
+        let p: Vec<f64> = aligned.iter().map(|(_, p, _)| *p).collect();
+        let b: Vec<f64> = aligned.iter().map(|(_, _, b)| *b).collect();
+        let portfolio_return = compound(&p);
+        let benchmark_return = compound(&b);
+
+        let (mean_p, mean_b) = (mean(&p), mean(&b));
+        let (mut cov, mut var_p, mut var_b) = (0.0, 0.0, 0.0);
+        for (x, y) in p.iter().zip(&b) {
+            cov += (x - mean_p) * (y - mean_b);
+            var_p += (x - mean_p).powi(2);
+            var_b += (y - mean_b).powi(2);
+        }
+        let sample = n >= 2;
+        let beta = (sample && var_b > 0.0).then(|| cov / var_b);
+        let correlation = (sample && var_b > 0.0 && var_p > 0.0).then(|| cov / (var_p * var_b).sqrt());
+        let daily_rf = self.risk_free_rate / TRADING_DAYS_PER_YEAR;
+        let alpha = beta.map(|beta| ((mean_p - daily_rf) - beta * (mean_b - daily_rf)) * TRADING_DAYS_PER_YEAR);
+
+        let active: Vec<f64> = p.iter().zip(&b).map(|(x, y)| x - y).collect();
+        let tracking_error = sample.then(|| sample_std_dev(&active) * TRADING_DAYS_PER_YEAR.sqrt());
+        let information_ratio = tracking_error
+            .filter(|te| *te > 0.0)
+            .map(|te| mean(&active) * TRADING_DAYS_PER_YEAR / te);
+
+        BenchmarkStatistics {
+            start_date: aligned[0].0,
+            end_date: aligned[n - 1].0,
+            observations: n,
+            portfolio_return,
+            benchmark_return,
+            excess_return: portfolio_return - benchmark_return,
+            beta,
+            alpha,
+            correlation,
+            r_squared: correlation.map(|c| c * c),
+            tracking_error,
+            information_ratio,
+            up_capture: capture_ratio(aligned, |b| b > 0.0),
+            down_capture: capture_ratio(aligned, |b| b < 0.0),
+        }
+    }
+}
+
+impl Default for BenchmarkAnalyzer {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
+pub fn align_returns(portfolio: &[(NaiveDate, f64)], benchmark: &[(NaiveDate, f64)]) -> Vec<(NaiveDate, f64, f64)> {
+    let by_date: HashMap<NaiveDate, f64> = benchmark.iter().copied().collect();
+    let mut aligned: Vec<(NaiveDate, f64, f64)> = portfolio
+        .iter()
+        .filter_map(|(date, p)| by_date.get(date).map(|b| (*date, *p, *b)))
+        .collect();
+    aligned.sort_by_key(|(date, _, _)| *date);
+    aligned
+}
+
+fn capture_ratio(aligned: &[(NaiveDate, f64, f64)], include: impl Fn(f64) -> bool) -> Option<f64> {
+    let (p, b): (Vec<f64>, Vec<f64>) = aligned.iter().filter(|(_, _, b)| include(*b)).map(|(_, p, b)| (*p, *b)).unzip();
+    if b.is_empty() {
+        return None;
+    }
+    let geometric_mean = |r: &[f64]| (1.0 + compound(r)).powf(1.0 / r.len() as f64) - 1.0;
+    let benchmark_mean = geometric_mean(&b);
+    (benchmark_mean != 0.0).then(|| geometric_mean(&p) / benchmark_mean)
+}
+
+fn compound(returns: &[f64]) -> f64 {
+    returns.iter().fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 }
+}
+
+fn sample_std_dev(values: &[f64]) -> f64 {
+    let m = mean(values);
+    (values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+}
@@ -0,0 +1,77 @@
+use crate::{DailyCloses, LedgerEntry, TransactionKind};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CorporateActionKind {
+    // `ratio` new units per old unit: 4 for a 4:1 split, 0.1 for a 1:10 reverse split
+    Split { ratio: f64 },
+    // Holdings continue under `new_symbol` with the same lots
+    SymbolChange { new_symbol: String },
+    CashDividend { amount_per_share: f64 },
+    // `ratio` new units per unit held, at no cost
+    StockDividend { ratio: f64 },
+    // `ratio` units of `new_symbol` per unit held, taking `cost_allocation` (0 to 1) of the parent's cost basis
+    SpinOff { new_symbol: String, ratio: f64, cost_allocation: f64 },
+}
+
+impl CorporateActionKind {
+    // Units after the action per unit before it (1 for actions that do not change the count)
+    pub fn unit_ratio(&self) -> f64 {
+        match self {
+            CorporateActionKind::Split { ratio } => *ratio,
+            CorporateActionKind::StockDividend { ratio } => 1.0 + ratio,
+            _ => 1.0,
+        }
+    }
+}
+
+// Effective from the start of `ex_date`: holdings at the previous close are entitled, and ledger entries on the ex-date come after it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorporateAction {
+    pub id: String,
+    pub symbol: String,
+    pub ex_date: NaiveDate,
+    #[serde(flatten)]
+    pub kind: CorporateActionKind,
+}
+
+impl CorporateAction {
+    // Whether a ledger entry records the same event, which would count it twice: a DIVIDEND for
+    // a cash dividend, or a SPLIT for a split or stock dividend, of the symbol on the ex-date
+    pub fn duplicates(&self, entry: &LedgerEntry) -> bool {
+        let same_kind = match entry.kind {
+            TransactionKind::Dividend => matches!(self.kind, CorporateActionKind::CashDividend { .. }),
+            TransactionKind::Split => {
+                matches!(self.kind, CorporateActionKind::Split { .. } | CorporateActionKind::StockDividend { .. })
+            }
+            _ => false,
+        };
+        same_kind && entry.symbol.eq_ignore_ascii_case(&self.symbol) && entry.timestamp.date_naive() == self.ex_date
+    }
+}
+
+// Product of the unit ratios of the symbol's splits and stock dividends after `date`
+pub fn split_factor(actions: &[CorporateAction], symbol: &str, date: NaiveDate) -> f64 {
+    actions
+        .iter()
+        .filter(|a| a.symbol == symbol && a.ex_date > date)
+        .map(|a| a.kind.unit_ratio())
+        .filter(|r| *r > 0.0)
+        .product()
+}
+
+// Returns across an ex-date only reflect the price move
+pub fn split_adjusted_closes(closes: &DailyCloses, actions: &[CorporateAction]) -> DailyCloses {
+    closes
+        .iter()
+        .map(|(symbol, series)| {
+            let adjusted = series
+                .iter()
+                .map(|(date, close)| (*date, close / split_factor(actions, symbol, *date)))
+                .collect();
+            (symbol.clone(), adjusted)
+        })
+        .collect()
+}
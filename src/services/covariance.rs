@@ -0,0 +1,271 @@
+// Covariance and correlation estimation for risk calculations
+
+use crate::{Result, ValuationError};
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum CovarianceMethod {
+    #[default]
+    Sample,
+    // Exponentially weighted (RiskMetrics uses lambda = 0.94 for daily data)
+    Ewma { lambda: f64 },
+    // Sample covariance shrunk towards a scaled identity (Ledoit-Wolf, 2004)
+    LedoitWolf,
+}
+
+// Smallest eigenvalue kept by the repair so the result stays Cholesky-factorizable
+const MIN_EIGENVALUE: f64 = 1e-10;
+const HIGHAM_MAX_ITERATIONS: usize = 100;
+const HIGHAM_TOLERANCE: f64 = 1e-10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CovarianceEstimator {
+    method: CovarianceMethod,
+    repair: bool,
+}
+
+impl CovarianceEstimator {
+    pub fn new(method: CovarianceMethod, repair: bool) -> Self {
+        Self { method, repair }
+    }
+
+    // Estimates the covariance matrix of `returns_matrix` (rows are assets)
+    pub fn estimate(&self, returns_matrix: &[Vec<f64>]) -> Result<na::DMatrix<f64>> {
+        let n_observations = validate(returns_matrix)?;
+        let complete = returns_matrix.iter().all(|r| r.iter().all(|v| !v.is_nan()));
+        let covariance = match self.method {
+            CovarianceMethod::Sample => {
+                let weights = vec![1.0; n_observations];
+                if complete {
+                    weighted_covariance(returns_matrix, &weights, true)
+                } else {
+                    pairwise_covariance(returns_matrix, &weights, true)?
+                }
+            }
+            CovarianceMethod::Ewma { lambda } => {
+                if !(lambda > 0.0 && lambda < 1.0) {
+                    return Err(ValuationError::RiskCalculation(format!("EWMA lambda must be in (0, 1), got {}", lambda)));
+                }
+                // Most recent observation (last column) has weight 1
+                let weights: Vec<f64> = (0..n_observations)
+                    .map(|t| lambda.powi((n_observations - 1 - t) as i32))
+                    .collect();
+                if complete {
+                    weighted_covariance(returns_matrix, &weights, false)
+                } else {
+                    pairwise_covariance(returns_matrix, &weights, false)?
+                }
+            }
+            CovarianceMethod::LedoitWolf => ledoit_wolf(returns_matrix)?,
+        };
+
+        if self.repair {
+            Ok(repair_covariance(&covariance))
+        } else {
+            Ok(covariance)
+        }
+    }
+
+    pub fn estimate_correlation(&self, returns_matrix: &[Vec<f64>]) -> Result<na::DMatrix<f64>> {
+        Ok(covariance_to_correlation(&self.estimate(returns_matrix)?).0)
+    }
+}
+
+impl Default for CovarianceEstimator {
+    fn default() -> Self {
+        Self::new(CovarianceMethod::Sample, true)
+    }
+}
+
+fn validate(returns_matrix: &[Vec<f64>]) -> Result<usize> {
+    if returns_matrix.is_empty() {
+        return Err(ValuationError::RiskCalculation("Empty returns matrix".to_string()));
+    }
+    let n_observations = returns_matrix[0].len();
+    if returns_matrix.iter().any(|r| r.len() != n_observations) {
+        return Err(ValuationError::RiskCalculation("Inconsistent number of observations".to_string()));
+    }
+    if n_observations < 2 {
+        return Err(ValuationError::RiskCalculation("Insufficient observations for covariance".to_string()));
+    }
+    Ok(n_observations)
+}
+
+// Weighted covariance of a matrix without gaps as a single X' W X product
+fn weighted_covariance(returns_matrix: &[Vec<f64>], weights: &[f64], demean: bool) -> na::DMatrix<f64> {
+    let n_assets = returns_matrix.len();
+    let n_observations = weights.len();
+    let total_weight: f64 = weights.iter().sum();
+    let weights = na::DVector::from_column_slice(weights);
+
+    let mut x = na::DMatrix::from_fn(n_observations, n_assets, |t, i| returns_matrix[i][t]);
+    if demean {
+        for mut column in x.column_iter_mut() {
+            let mean = column.dot(&weights) / total_weight;
+            column.add_scalar_mut(-mean);
+        }
+    }
+    for (mut row, weight) in x.row_iter_mut().zip(weights.iter()) {
+        row *= weight.sqrt();
+    }
+    let denominator = if demean {
+        total_weight - weights.norm_squared() / total_weight
+    } else {
+        total_weight
+    };
+    x.transpose() * x / denominator
+}
+
+// Each entry only uses the observations on which both assets are present
+fn pairwise_covariance(returns_matrix: &[Vec<f64>], weights: &[f64], demean: bool) -> Result<na::DMatrix<f64>> {
+    let n_assets = returns_matrix.len();
+    let mut covariance = na::DMatrix::zeros(n_assets, n_assets);
+
+    for i in 0..n_assets {
+        for j in i..n_assets {
+            let (x, y) = (&returns_matrix[i], &returns_matrix[j]);
+            let pairs: Vec<(f64, f64, f64)> = (0..weights.len())
+                .filter(|&t| !x[t].is_nan() && !y[t].is_nan())
+                .map(|t| (x[t], y[t], weights[t]))
+                .collect();
+            if pairs.len() < 2 {
+                return Err(ValuationError::RiskCalculation(format!(
+                    "Insufficient overlapping observations for assets {} and {}", i, j
+                )));
+            }
+
+            let total_weight: f64 = pairs.iter().map(|p| p.2).sum();
+            let (mean_x, mean_y) = if demean {
+                (
+                    pairs.iter().map(|p| p.0 * p.2).sum::<f64>() / total_weight,
+                    pairs.iter().map(|p| p.1 * p.2).sum::<f64>() / total_weight,
+                )
+            } else {
+                (0.0, 0.0)
+            };
+            let cross: f64 = pairs.iter().map(|p| p.2 * (p.0 - mean_x) * (p.1 - mean_y)).sum();
+            let value = if demean {
+                // Reliability-weights correction; reduces to 1 / (T - 1) for equal weights
+                let sum_sq: f64 = pairs.iter().map(|p| p.2 * p.2).sum();
+                cross / (total_weight - sum_sq / total_weight)
+            } else {
+                cross / total_weight
+            };
+            covariance[(i, j)] = value;
+            covariance[(j, i)] = value;
+        }
+    }
+    Ok(covariance)
+}
+
+// Ledoit-Wolf shrinkage of the pairwise sample covariance towards mu * I, mu = tr(S) / n
+fn ledoit_wolf(returns_matrix: &[Vec<f64>]) -> Result<na::DMatrix<f64>> {
+    let n_assets = returns_matrix.len();
+    let n_observations = returns_matrix[0].len();
+
+    // Pairwise-complete demeaned returns and 1/T sample covariance
+    let means: Vec<f64> = returns_matrix
+        .iter()
+        .map(|r| {
+            let present: Vec<f64> = r.iter().copied().filter(|v| !v.is_nan()).collect();
+            present.iter().sum::<f64>() / present.len().max(1) as f64
+        })
+        .collect();
+    let centered: Vec<Vec<f64>> = returns_matrix
+        .iter()
+        .zip(&means)
+        .map(|(r, m)| r.iter().map(|v| v - m).collect())
+        .collect();
+
+    let mut sample = na::DMatrix::zeros(n_assets, n_assets);
+    let mut pi_hat = 0.0;
+    for i in 0..n_assets {
+        for j in 0..n_assets {
+            let products: Vec<f64> = (0..n_observations)
+                .filter(|&t| !centered[i][t].is_nan() && !centered[j][t].is_nan())
+                .map(|t| centered[i][t] * centered[j][t])
+                .collect();
+            if products.len() < 2 {
+                return Err(ValuationError::RiskCalculation(format!(
+                    "Insufficient overlapping observations for assets {} and {}", i, j
+                )));
+            }
+            let count = products.len() as f64;
+            let s_ij = products.iter().sum::<f64>() / count;
+            sample[(i, j)] = s_ij;
+            // Estimated variance of the (i, j) sample covariance entry
+            pi_hat += products.iter().map(|p| (p - s_ij).powi(2)).sum::<f64>() / (count * count);
+        }
+    }
+
+    let mu = sample.trace() / n_assets as f64;
+    let target = na::DMatrix::identity(n_assets, n_assets) * mu;
+    let d_squared = (&sample - &target).norm_squared();
+    let intensity = if d_squared > 0.0 { (pi_hat.min(d_squared)) / d_squared } else { 1.0 };
+
+    Ok(&target * intensity + &sample * (1.0 - intensity))
+}
+
+// Splits a covariance matrix into its correlation matrix and the standard deviations
+pub fn covariance_to_correlation(covariance: &na::DMatrix<f64>) -> (na::DMatrix<f64>, na::DVector<f64>) {
+    let std_devs = covariance.diagonal().map(|v| v.max(0.0).sqrt());
+    let n = covariance.nrows();
+    let correlation = na::DMatrix::from_fn(n, n, |i, j| {
+        if i == j {
+            1.0
+        } else if std_devs[i] > 0.0 && std_devs[j] > 0.0 {
+            covariance[(i, j)] / (std_devs[i] * std_devs[j])
+        } else {
+            0.0
+        }
+    });
+    (correlation, std_devs)
+}
+
+pub fn is_positive_semi_definite(matrix: &na::DMatrix<f64>) -> bool {
+    na::SymmetricEigen::new(matrix.clone()).eigenvalues.iter().all(|v| *v >= -1e-12)
+}
+
+// Higham's alternating projections with Dykstra's correction, eigenvalues floored so the result is positive definite
+pub fn nearest_correlation_matrix(correlation: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    let n = correlation.nrows();
+    let symmetric = (correlation + correlation.transpose()) * 0.5;
+    let mut y = symmetric.clone();
+    let mut correction = na::DMatrix::zeros(n, n);
+
+    for _ in 0..HIGHAM_MAX_ITERATIONS {
+        let r = &y - &correction;
+        let x = project_psd(&r, 0.0);
+        correction = &x - &r;
+        let mut next = x;
+        next.fill_diagonal(1.0);
+        let change = (&next - &y).norm() / y.norm().max(1.0);
+        y = next;
+        if change < HIGHAM_TOLERANCE {
+            break;
+        }
+    }
+
+    // Floor tiny/negative eigenvalues left by the tolerance and renormalize the diagonal
+    let floored = project_psd(&y, MIN_EIGENVALUE);
+    covariance_to_correlation(&floored).0
+}
+
+// Rebuilt from the nearest correlation matrix and the original variances when not positive definite
+pub fn repair_covariance(covariance: &na::DMatrix<f64>) -> na::DMatrix<f64> {
+    if covariance.clone().cholesky().is_some() {
+        return covariance.clone();
+    }
+    let (correlation, std_devs) = covariance_to_correlation(covariance);
+    let repaired = nearest_correlation_matrix(&correlation);
+    let scale = na::DMatrix::from_diagonal(&std_devs);
+    &scale * repaired * &scale
+}
+
+fn project_psd(matrix: &na::DMatrix<f64>, min_eigenvalue: f64) -> na::DMatrix<f64> {
+    let eigen = na::SymmetricEigen::new((matrix + matrix.transpose()) * 0.5);
+    let clipped = eigen.eigenvalues.map(|v| v.max(min_eigenvalue));
+    &eigen.eigenvectors * na::DMatrix::from_diagonal(&clipped) * eigen.eigenvectors.transpose()
+}
@@ -0,0 +1,115 @@
+use crate::{Result, ValuationError};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub type DailyCloses = HashMap<String, BTreeMap<NaiveDate, f64>>;
+
+// Daily simple returns for several symbols on a common set of dates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReturnSeries {
+    pub symbols: Vec<String>,
+    pub dates: Vec<NaiveDate>,
+    pub returns: Vec<Vec<f64>>,
+}
+
+impl ReturnSeries {
+    pub fn len(&self) -> usize {
+        self.dates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dates.is_empty()
+    }
+
+    pub fn observation(&self, t: usize) -> HashMap<&str, f64> {
+        self.symbols
+            .iter()
+            .zip(&self.returns)
+            .map(|(symbol, series)| (symbol.as_str(), series[t]))
+            .collect()
+    }
+
+    pub fn series(&self, symbol: &str) -> Option<&[f64]> {
+        self.symbols.iter().position(|s| s == symbol).map(|i| self.returns[i].as_slice())
+    }
+}
+
+// Dates on which every symbol has a close, keeping the most recent `lookback` returns
+pub fn aligned_daily_returns(closes: &DailyCloses, lookback: usize) -> Result<ReturnSeries> {
+    let mut symbols: Vec<String> = closes.keys().cloned().collect();
+    symbols.sort();
+    if symbols.is_empty() {
+        return Err(ValuationError::MarketData("No price history supplied".to_string()));
+    }
+
+    let mut common: BTreeSet<NaiveDate> = closes[&symbols[0]].keys().copied().collect();
+    for symbol in &symbols[1..] {
+        common.retain(|d| closes[symbol].contains_key(d));
+    }
+    let dates: Vec<NaiveDate> = common.into_iter().collect();
+    if dates.len() < 2 {
+        return Err(ValuationError::MarketData(
+            "Insufficient overlapping price history to build returns".to_string(),
+        ));
+    }
+
+    let start = dates.len().saturating_sub(lookback + 1);
+    let window = &dates[start..];
+    let returns = symbols
+        .iter()
+        .map(|symbol| {
+            let series = &closes[symbol];
+            window
+                .windows(2)
+                .map(|pair| {
+                    let (prev, curr) = (series[&pair[0]], series[&pair[1]]);
+                    if prev != 0.0 { curr / prev - 1.0 } else { 0.0 }
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(ReturnSeries {
+        symbols,
+        dates: window[1..].to_vec(),
+        returns,
+    })
+}
+
+// Union of all dates, keeping the most recent `lookback` returns
+pub fn daily_returns_with_gaps(closes: &DailyCloses, lookback: usize) -> Result<ReturnSeries> {
+    let mut symbols: Vec<String> = closes.keys().cloned().collect();
+    symbols.sort();
+    if symbols.is_empty() {
+        return Err(ValuationError::MarketData("No price history supplied".to_string()));
+    }
+
+    let all_dates: BTreeSet<NaiveDate> = closes.values().flat_map(|series| series.keys().copied()).collect();
+    let dates: Vec<NaiveDate> = all_dates.into_iter().collect();
+    if dates.len() < 2 {
+        return Err(ValuationError::MarketData("Insufficient price history to build returns".to_string()));
+    }
+
+    let start = dates.len().saturating_sub(lookback + 1);
+    let window = &dates[start..];
+    let returns = symbols
+        .iter()
+        .map(|symbol| {
+            let series = &closes[symbol];
+            window
+                .windows(2)
+                .map(|pair| match (series.get(&pair[0]), series.get(&pair[1])) {
+                    (Some(&prev), Some(&curr)) if prev != 0.0 => curr / prev - 1.0,
+                    _ => f64::NAN,
+                })
+                .collect()
+        })
+        .collect();
+
+    Ok(ReturnSeries {
+        symbols,
+        dates: window[1..].to_vec(),
+        returns,
+    })
+}
@@ -0,0 +1,637 @@
+use crate::{CashMovement, CorporateAction, CorporateActionKind, LedgerTrade, LotBook, ReliefMethod};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TransactionKind {
+    Buy,
+    Sell,
+    // Sells units not held, opening a short lot
+    Short,
+    Cover,
+    Dividend,
+    Interest,
+    Fee,
+    TaxWithholding,
+    Deposit,
+    Withdrawal,
+    // Units received from another account at their cost basis
+    TransferIn,
+    TransferOut,
+    // `quantity` new units per old unit
+    Split,
+    // Sets the position to `quantity` at `price` per unit
+    Adjust,
+}
+
+impl TransactionKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.trim().to_uppercase())).ok()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionKind::Buy => "BUY",
+            TransactionKind::Sell => "SELL",
+            TransactionKind::Short => "SHORT",
+            TransactionKind::Cover => "COVER",
+            TransactionKind::Dividend => "DIVIDEND",
+            TransactionKind::Interest => "INTEREST",
+            TransactionKind::Fee => "FEE",
+            TransactionKind::TaxWithholding => "TAX_WITHHOLDING",
+            TransactionKind::Deposit => "DEPOSIT",
+            TransactionKind::Withdrawal => "WITHDRAWAL",
+            TransactionKind::TransferIn => "TRANSFER_IN",
+            TransactionKind::TransferOut => "TRANSFER_OUT",
+            TransactionKind::Split => "SPLIT",
+            TransactionKind::Adjust => "ADJUST",
+        }
+    }
+
+    // Moves cash only, by an amount rather than a quantity of units
+    pub fn is_cash(&self) -> bool {
+        matches!(
+            self,
+            TransactionKind::Dividend
+                | TransactionKind::Interest
+                | TransactionKind::Fee
+                | TransactionKind::TaxWithholding
+                | TransactionKind::Deposit
+                | TransactionKind::Withdrawal
+        )
+    }
+
+    // Made through the position endpoints rather than posted by clients
+    pub fn is_internal(&self) -> bool {
+        *self == TransactionKind::Adjust
+    }
+
+    // Needs a symbol (cash entries other than dividends do not)
+    pub fn requires_symbol(&self) -> bool {
+        !self.is_cash() || *self == TransactionKind::Dividend
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub id: String,
+    pub kind: TransactionKind,
+    // Empty for cash entries without an instrument
+    pub symbol: String,
+    pub quantity: f64,
+    // Price per unit; the cost per unit for transfers in and adjustments
+    pub price: Option<f64>,
+    // Cash amount of cash entries
+    pub amount: Option<f64>,
+    pub timestamp: DateTime<Utc>,
+    // Lots a sale, cover or transfer out closes first
+    #[serde(default)]
+    pub lot_ids: Vec<String>,
+}
+
+impl LedgerEntry {
+    // Cash moved by a cash entry, as a positive amount
+    pub fn cash_amount(&self) -> f64 {
+        self.amount
+            .unwrap_or_else(|| self.quantity * self.price.unwrap_or(0.0))
+            .abs()
+    }
+
+    // Checks the fields the entry's kind needs, independently of the account
+    pub fn validate(&self) -> std::result::Result<(), LedgerViolation> {
+        let kind = self.kind;
+        if kind.requires_symbol() && self.symbol.trim().is_empty() {
+            return Err(LedgerViolation::MissingSymbol(kind.as_str()));
+        }
+        if !self.lot_ids.is_empty()
+            && !matches!(kind, TransactionKind::Sell | TransactionKind::Cover | TransactionKind::TransferOut)
+        {
+            return Err(LedgerViolation::LotIdsNotAllowed);
+        }
+        if kind.is_cash() {
+            let amount = self.amount.unwrap_or_else(|| self.quantity * self.price.unwrap_or(0.0));
+            return if amount.is_finite() && amount > 0.0 { Ok(()) } else { Err(LedgerViolation::InvalidAmount) };
+        }
+
+        let quantity_ok = self.quantity.is_finite()
+            && (self.quantity > 0.0 || (kind == TransactionKind::Adjust && self.quantity == 0.0));
+        if !quantity_ok {
+            return Err(LedgerViolation::InvalidQuantity);
+        }
+        match (kind, self.price) {
+            // Trades settle at a price
+            (TransactionKind::Buy | TransactionKind::Sell | TransactionKind::Short | TransactionKind::Cover, None) => {
+                Err(LedgerViolation::MissingPrice(kind.as_str()))
+            }
+            (TransactionKind::Buy | TransactionKind::Sell | TransactionKind::Short | TransactionKind::Cover, Some(price))
+                if !(price.is_finite() && price > 0.0) =>
+            {
+                Err(LedgerViolation::InvalidPrice)
+            }
+            // Transfers in need a cost basis, which may be zero
+            (TransactionKind::TransferIn, None) => Err(LedgerViolation::MissingPrice(kind.as_str())),
+            (_, Some(price)) if !(price.is_finite() && price >= 0.0) => Err(LedgerViolation::InvalidPrice),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum LedgerViolation {
+    #[error("symbol is required for {0}")]
+    MissingSymbol(&'static str),
+    #[error("quantity must be a positive number")]
+    InvalidQuantity,
+    #[error("price is required for {0}")]
+    MissingPrice(&'static str),
+    #[error("price must be a positive number")]
+    InvalidPrice,
+    #[error("amount (or quantity and price) must be a positive number")]
+    InvalidAmount,
+    #[error("lot_ids are only allowed on SELL, COVER and TRANSFER_OUT")]
+    LotIdsNotAllowed,
+    #[error("lot_ids cannot be used with average cost relief")]
+    LotIdsWithAverageCost,
+    #[error("lot {0} is not open for this symbol")]
+    LotNotOpen(String),
+    #[error("corporate action {0} already records this event")]
+    DuplicatesCorporateAction(String),
+    #[error("short sales are not allowed in this portfolio")]
+    ShortNotAllowed,
+    #[error("quantity {requested} exceeds the {held} units held")]
+    Oversell { requested: f64, held: f64 },
+    #[error("quantity {requested} exceeds the {short} units short")]
+    Overcover { requested: f64, short: f64 },
+}
+
+impl LedgerViolation {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LedgerViolation::MissingSymbol(_) => "missing_symbol",
+            LedgerViolation::InvalidQuantity => "invalid_quantity",
+            LedgerViolation::MissingPrice(_) => "missing_price",
+            LedgerViolation::InvalidPrice => "invalid_price",
+            LedgerViolation::InvalidAmount => "invalid_amount",
+            LedgerViolation::LotIdsNotAllowed => "lot_ids_not_allowed",
+            LedgerViolation::LotIdsWithAverageCost => "lot_ids_with_average_cost",
+            LedgerViolation::LotNotOpen(_) => "lot_not_open",
+            LedgerViolation::DuplicatesCorporateAction(_) => "duplicate_corporate_action",
+            LedgerViolation::ShortNotAllowed => "short_not_allowed",
+            LedgerViolation::Oversell { .. } => "oversell",
+            LedgerViolation::Overcover { .. } => "overcover",
+        }
+    }
+
+    // Conflicts with the account's holdings rather than a malformed entry
+    pub fn is_conflict(&self) -> bool {
+        matches!(
+            self,
+            LedgerViolation::LotNotOpen(_)
+                | LedgerViolation::ShortNotAllowed
+                | LedgerViolation::Oversell { .. }
+                | LedgerViolation::Overcover { .. }
+                | LedgerViolation::DuplicatesCorporateAction(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CashSummary {
+    pub deposits: f64,
+    pub withdrawals: f64,
+    // Debits beyond the cash balance, treated as deposits
+    pub implied_deposits: f64,
+    pub dividends: f64,
+    pub interest: f64,
+    pub fees: f64,
+    pub taxes_withheld: f64,
+    // Paid for purchases and covers
+    pub purchases: f64,
+    // Received from sales and short sales
+    pub sales: f64,
+}
+
+impl CashSummary {
+    // Dividends and interest net of fees and withholding taxes
+    pub fn net_income(&self) -> f64 {
+        self.dividends + self.interest - self.fees - self.taxes_withheld
+    }
+}
+
+// Open lots, realized sales and cash of a replayed ledger, plus the trades and cash movements for its NAV
+#[derive(Debug, Clone, Default)]
+pub struct Account {
+    pub lots: LotBook,
+    pub cash: f64,
+    pub summary: CashSummary,
+    allow_short: bool,
+    trades: Vec<LedgerTrade>,
+    movements: Vec<CashMovement>,
+}
+
+impl Account {
+    pub fn new(method: ReliefMethod) -> Self {
+        Self { lots: LotBook::new().with_relief_method(method), ..Default::default() }
+    }
+
+    pub fn with_short_sales(mut self, allow_short: bool) -> Self {
+        self.allow_short = allow_short;
+        self
+    }
+
+    pub fn allows_short_sales(&self) -> bool {
+        self.allow_short
+    }
+
+    // Long units held (0 when the position is short)
+    pub fn long_quantity(&self, symbol: &str) -> f64 {
+        self.lots.lots(symbol).iter().map(|l| l.quantity.max(0.0)).sum()
+    }
+
+    // Short units owed, as a positive number
+    pub fn short_quantity(&self, symbol: &str) -> f64 {
+        self.lots.lots(symbol).iter().map(|l| (-l.quantity).max(0.0)).sum()
+    }
+
+    // Checks `entry` as the next entry of the ledger
+    pub fn validate(&self, entry: &LedgerEntry) -> std::result::Result<(), LedgerViolation> {
+        entry.validate()?;
+        let symbol = entry.symbol.as_str();
+        if !entry.lot_ids.is_empty() {
+            if self.lots.relief_method() == ReliefMethod::AverageCost {
+                return Err(LedgerViolation::LotIdsWithAverageCost);
+            }
+            let short = entry.kind == TransactionKind::Cover;
+            let open = |id: &String| self.lots.lots(symbol).iter().any(|l| &l.id == id && (l.quantity < 0.0) == short);
+            if let Some(unknown) = entry.lot_ids.iter().find(|id| !open(id)) {
+                return Err(LedgerViolation::LotNotOpen(unknown.clone()));
+            }
+        }
+
+        // Tolerance for quantities rebuilt through splits and partial sales
+        const TOLERANCE: f64 = 1e-9;
+        match entry.kind {
+            TransactionKind::Short if !self.allow_short => Err(LedgerViolation::ShortNotAllowed),
+            TransactionKind::Sell | TransactionKind::TransferOut => {
+                let held = self.long_quantity(symbol);
+                let may_short = entry.kind == TransactionKind::Sell && self.allow_short;
+                if entry.quantity > held + TOLERANCE && !may_short {
+                    Err(LedgerViolation::Oversell { requested: entry.quantity, held })
+                } else {
+                    Ok(())
+                }
+            }
+            TransactionKind::Cover => {
+                let short = self.short_quantity(symbol);
+                if entry.quantity > short + TOLERANCE {
+                    Err(LedgerViolation::Overcover { requested: entry.quantity, short })
+                } else {
+                    Ok(())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Replays `entries` in order, applying each corporate action up to `through` at the start of its ex-date
+    pub fn replay(mut self, entries: &[LedgerEntry], actions: &[CorporateAction], through: NaiveDate) -> Self {
+        let _ = self.replay_entries(entries, actions, through, false);
+        self
+    }
+
+    // Replays `entries` like `replay`, validating each one against the holdings before it
+    pub fn replay_validated(
+        mut self,
+        entries: &[LedgerEntry],
+        actions: &[CorporateAction],
+        through: NaiveDate,
+    ) -> std::result::Result<Self, LedgerEditError> {
+        self.replay_entries(entries, actions, through, true)?;
+        Ok(self)
+    }
+
+    fn replay_entries(
+        &mut self,
+        entries: &[LedgerEntry],
+        actions: &[CorporateAction],
+        through: NaiveDate,
+        validate: bool,
+    ) -> std::result::Result<(), LedgerEditError> {
+        let mut effective: Vec<&CorporateAction> = actions.iter().filter(|a| a.ex_date <= through).collect();
+        effective.sort_by_key(|a| a.ex_date);
+        let mut pending = effective.into_iter().peekable();
+        for entry in entries {
+            while let Some(action) = pending.next_if(|a| a.ex_date <= entry.timestamp.date_naive()) {
+                self.apply_corporate_action(action);
+            }
+            if validate {
+                let violation = match actions.iter().find(|a| a.duplicates(entry)) {
+                    Some(action) => Err(LedgerViolation::DuplicatesCorporateAction(action.id.clone())),
+                    None => self.validate(entry),
+                };
+                violation.map_err(|violation| LedgerEditError::Violation { transaction_id: entry.id.clone(), violation })?;
+            }
+            self.apply(entry);
+        }
+        for action in pending {
+            self.apply_corporate_action(action);
+        }
+        Ok(())
+    }
+
+    // Applies the next entry of the ledger (entries must be applied in ledger order)
+    pub fn apply(&mut self, entry: &LedgerEntry) {
+        let symbol = entry.symbol.as_str();
+        let quantity = entry.quantity.max(0.0);
+        let price = entry.price.unwrap_or(0.0);
+        let at = entry.timestamp;
+        let date = at.date_naive();
+        match entry.kind {
+            TransactionKind::Buy => {
+                let short = self.short_quantity(symbol);
+                if short > 0.0 {
+                    self.lots.cover(entry.id.clone(), symbol, quantity.min(short), price, &[], at);
+                }
+                self.lots.open(entry.id.clone(), symbol, quantity - short.min(quantity), price, at);
+                self.trade(date, symbol, quantity, price);
+                self.summary.purchases += quantity * price;
+                self.debit(date, quantity * price);
+            }
+            TransactionKind::Sell => {
+                let mut sold = self.lots.sell(entry.id.clone(), symbol, quantity, price, &entry.lot_ids, at);
+                if self.allow_short && quantity > sold {
+                    self.lots.open_short(entry.id.clone(), symbol, quantity - sold, price, at);
+                    sold = quantity;
+                }
+                self.trade(date, symbol, -sold, price);
+                self.summary.sales += sold * price;
+                self.cash += sold * price;
+            }
+            TransactionKind::Short => {
+                self.lots.open_short(entry.id.clone(), symbol, quantity, price, at);
+                self.trade(date, symbol, -quantity, price);
+                self.summary.sales += quantity * price;
+                self.cash += quantity * price;
+            }
+            TransactionKind::Cover => {
+                let covered = self.lots.cover(entry.id.clone(), symbol, quantity, price, &entry.lot_ids, at);
+                self.trade(date, symbol, covered, price);
+                self.summary.purchases += covered * price;
+                self.debit(date, covered * price);
+            }
+            TransactionKind::TransferIn => {
+                self.lots.open(entry.id.clone(), symbol, quantity, price, at);
+                self.transfer(date, symbol, quantity, price);
+            }
+            TransactionKind::TransferOut => {
+                let closed = self.lots.relieve(symbol, quantity, &entry.lot_ids);
+                let moved: f64 = closed.iter().map(|l| l.quantity).sum();
+                // Delivered at the given price, or at cost
+                let value = match entry.price {
+                    Some(price) => moved * price,
+                    None => closed.iter().map(|l| l.quantity * l.cost).sum(),
+                };
+                let unit_value = if moved > 0.0 { value / moved } else { 0.0 };
+                self.transfer(date, symbol, -moved, unit_value);
+            }
+            TransactionKind::Split => {
+                let change = self.lots.split(symbol, entry.quantity);
+                self.trade(date, symbol, change, 0.0);
+            }
+            TransactionKind::Adjust => {
+                let before = self.lots.quantity(symbol);
+                self.lots.reset(entry.id.clone(), symbol, quantity, price, at);
+                self.transfer(date, symbol, quantity - before, price);
+            }
+            TransactionKind::Dividend => {
+                self.summary.dividends += entry.cash_amount();
+                self.income(date, entry.cash_amount());
+            }
+            TransactionKind::Interest => {
+                self.summary.interest += entry.cash_amount();
+                self.income(date, entry.cash_amount());
+            }
+            TransactionKind::Deposit => {
+                self.summary.deposits += entry.cash_amount();
+                self.cash += entry.cash_amount();
+                self.movement(date, entry.cash_amount(), true);
+            }
+            TransactionKind::Fee => {
+                self.summary.fees += entry.cash_amount();
+                self.income(date, -entry.cash_amount());
+            }
+            TransactionKind::TaxWithholding => {
+                self.summary.taxes_withheld += entry.cash_amount();
+                self.income(date, -entry.cash_amount());
+            }
+            TransactionKind::Withdrawal => {
+                self.summary.withdrawals += entry.cash_amount();
+                self.movement(date, -entry.cash_amount(), true);
+                self.debit(date, entry.cash_amount());
+            }
+        }
+    }
+
+    pub fn apply_corporate_action(&mut self, action: &CorporateAction) {
+        let symbol = action.symbol.as_str();
+        let date = action.ex_date;
+        match &action.kind {
+            CorporateActionKind::Split { .. } | CorporateActionKind::StockDividend { .. } => {
+                let change = self.lots.split(symbol, action.kind.unit_ratio());
+                self.trade(date, symbol, change, 0.0);
+            }
+            CorporateActionKind::SymbolChange { new_symbol } => {
+                // Moved at cost, which nets to nothing in cash and keeps the cost basis of
+                // replays that only see the trades
+                for lot in self.lots.rename(symbol, new_symbol) {
+                    self.trade(date, symbol, -lot.quantity, lot.cost);
+                    self.trade(date, new_symbol, lot.quantity, lot.cost);
+                }
+            }
+            CorporateActionKind::CashDividend { amount_per_share } => {
+                // Short positions pay the dividend
+                let amount = self.lots.quantity(symbol) * amount_per_share;
+                self.summary.dividends += amount;
+                self.income(date, amount);
+            }
+            CorporateActionKind::SpinOff { new_symbol, ratio, cost_allocation } => {
+                let received = self.lots.spin_off(symbol, new_symbol, *ratio, *cost_allocation);
+                self.trade(date, new_symbol, received, 0.0);
+            }
+        }
+    }
+
+    // Security trades, in ledger order, with the quantities actually moved
+    pub fn trades(&self) -> &[LedgerTrade] {
+        &self.trades
+    }
+
+    // Cash movements other than trade settlements, in ledger order
+    pub fn cash_movements(&self) -> &[CashMovement] {
+        &self.movements
+    }
+
+    fn trade(&mut self, date: NaiveDate, symbol: &str, quantity: f64, price: f64) {
+        if quantity == 0.0 {
+            return;
+        }
+        self.trades.push(LedgerTrade { date, symbol: symbol.to_string(), quantity, price });
+    }
+
+    // Units moved in or out of the account without settling in cash: an external flow of
+    // their value
+    fn transfer(&mut self, date: NaiveDate, symbol: &str, quantity: f64, price: f64) {
+        self.trade(date, symbol, quantity, price);
+        self.movement(date, quantity * price, true);
+    }
+
+    fn movement(&mut self, date: NaiveDate, amount: f64, external: bool) {
+        if amount != 0.0 {
+            self.movements.push(CashMovement { date, amount, external });
+        }
+    }
+
+    // Income (or, when negative, a charge) that stays inside the account
+    fn income(&mut self, date: NaiveDate, amount: f64) {
+        self.movement(date, amount, false);
+        if amount >= 0.0 {
+            self.cash += amount;
+        } else {
+            self.debit(date, -amount);
+        }
+    }
+
+    // Cash paid. A shortfall is funded by an implied deposit.
+    fn debit(&mut self, date: NaiveDate, amount: f64) {
+        self.cash -= amount;
+        if self.cash < 0.0 {
+            let shortfall = -self.cash;
+            self.cash = 0.0;
+            self.summary.implied_deposits += shortfall;
+            self.movement(date, shortfall, true);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LedgerRecord {
+    pub entry: LedgerEntry,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl LedgerRecord {
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+}
+
+// Live entries of stored records, in ledger order (by timestamp, then ID)
+pub fn live_entries(records: &[LedgerRecord]) -> Vec<LedgerEntry> {
+    let mut entries: Vec<LedgerEntry> = records.iter().filter(|r| !r.is_deleted()).map(|r| r.entry.clone()).collect();
+    entries.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+    entries
+}
+
+#[derive(Debug, Clone)]
+pub enum LedgerEdit {
+    Insert(LedgerEntry),
+    // Replaces the live entry with the same ID
+    Replace(LedgerEntry),
+    // Soft-deletes the live entry with this ID
+    Delete(String),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+        }
+    }
+}
+
+// Live entries after an edit, and the edited entry before and after it
+#[derive(Debug, Clone)]
+pub struct EditedLedger {
+    pub entries: Vec<LedgerEntry>,
+    pub action: AuditAction,
+    pub before: Option<LedgerEntry>,
+    pub after: Option<LedgerEntry>,
+}
+
+impl EditedLedger {
+    // First day whose holdings the edit changes
+    pub fn changed_from(&self) -> Option<NaiveDate> {
+        self.before.iter().chain(&self.after).map(|e| e.timestamp.date_naive()).min()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum LedgerEditError {
+    #[error("transaction {0} not found")]
+    NotFound(String),
+    #[error("transaction {0} is deleted")]
+    Deleted(String),
+    // An entry of the edited ledger that the holdings before it do not allow
+    #[error("transaction {transaction_id}: {violation}")]
+    Violation { transaction_id: String, violation: LedgerViolation },
+}
+
+impl LedgerEditError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LedgerEditError::NotFound(_) => "transaction_not_found",
+            LedgerEditError::Deleted(_) => "transaction_deleted",
+            LedgerEditError::Violation { violation, .. } => violation.code(),
+        }
+    }
+}
+
+impl LedgerEdit {
+    pub fn transaction_id(&self) -> &str {
+        match self {
+            LedgerEdit::Insert(entry) | LedgerEdit::Replace(entry) => &entry.id,
+            LedgerEdit::Delete(id) => id,
+        }
+    }
+
+    pub fn action(&self) -> AuditAction {
+        match self {
+            LedgerEdit::Insert(_) => AuditAction::Create,
+            LedgerEdit::Replace(_) => AuditAction::Update,
+            LedgerEdit::Delete(_) => AuditAction::Delete,
+        }
+    }
+
+    pub fn apply(&self, records: &[LedgerRecord]) -> std::result::Result<EditedLedger, LedgerEditError> {
+        let id = self.transaction_id();
+        let before = match self {
+            LedgerEdit::Insert(_) => None,
+            LedgerEdit::Replace(_) | LedgerEdit::Delete(_) => {
+                let record = records.iter().find(|r| r.entry.id == id).ok_or_else(|| LedgerEditError::NotFound(id.to_string()))?;
+                if record.is_deleted() {
+                    return Err(LedgerEditError::Deleted(id.to_string()));
+                }
+                Some(record.entry.clone())
+            }
+        };
+        let after = match self {
+            LedgerEdit::Insert(entry) | LedgerEdit::Replace(entry) => Some(entry.clone()),
+            LedgerEdit::Delete(_) => None,
+        };
+
+        let mut records: Vec<LedgerRecord> = records.iter().filter(|r| r.entry.id != id).cloned().collect();
+        records.extend(after.iter().map(|entry| LedgerRecord { entry: entry.clone(), deleted_at: None }));
+        Ok(EditedLedger { entries: live_entries(&records), action: self.action(), before, after })
+    }
+}
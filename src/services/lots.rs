@@ -0,0 +1,418 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lot {
+    pub id: String,
+    pub quantity: f64,
+    // Cost per unit (the sale price per unit of a short lot)
+    pub cost: f64,
+    pub opened_at: DateTime<Utc>,
+}
+
+impl Lot {
+    pub fn cost_basis(&self) -> f64 {
+        self.quantity * self.cost
+    }
+}
+
+// Part of a lot closed by a sale
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LotClosing {
+    pub lot_id: String,
+    pub quantity: f64,
+    // Cost per unit relieved (the average cost under average cost relief)
+    pub cost: f64,
+    pub opened_at: DateTime<Utc>,
+}
+
+// Realized result of one sale or short cover: the lots it closed, their cost and the proceeds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealizedTrade {
+    pub transaction_id: String,
+    pub symbol: String,
+    pub closed_at: DateTime<Utc>,
+    pub quantity: f64,
+    pub price: f64,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+    pub lots: Vec<LotClosing>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReliefMethod {
+    // Oldest lots first
+    #[default]
+    Fifo,
+    Lifo,
+    // Highest cost per unit first
+    Hifo,
+    // Oldest lots first, every unit at the average cost of the open lots
+    AverageCost,
+    // The lots named by the sale, then oldest first
+    SpecificLot,
+}
+
+// Open lots per symbol, built by replaying a portfolio's ledger in order
+#[derive(Debug, Clone, Default)]
+pub struct LotBook {
+    lots: HashMap<String, Vec<Lot>>,
+    method: ReliefMethod,
+    realized: Vec<RealizedTrade>,
+}
+
+impl LotBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_relief_method(mut self, method: ReliefMethod) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn relief_method(&self) -> ReliefMethod {
+        self.method
+    }
+
+    pub fn open(&mut self, id: String, symbol: &str, quantity: f64, cost: f64, opened_at: DateTime<Utc>) {
+        if quantity <= 0.0 {
+            return;
+        }
+        self.lots.entry(symbol.to_string()).or_default().push(Lot { id, quantity, cost, opened_at });
+    }
+
+    // Returns the quantity relieved from long lots; nothing is recorded when it is zero
+    pub fn sell(
+        &mut self,
+        transaction_id: String,
+        symbol: &str,
+        quantity: f64,
+        price: f64,
+        lot_ids: &[String],
+        closed_at: DateTime<Utc>,
+    ) -> f64 {
+        let lots = self.relieve(symbol, quantity, lot_ids);
+        let quantity: f64 = lots.iter().map(|l| l.quantity).sum();
+        // Nothing was held (e.g. a sale that only opens a short)
+        if quantity == 0.0 {
+            return 0.0;
+        }
+        let cost_basis: f64 = lots.iter().map(|l| l.quantity * l.cost).sum();
+        let proceeds = quantity * price;
+        self.realized.push(RealizedTrade {
+            transaction_id,
+            symbol: symbol.to_string(),
+            closed_at,
+            quantity,
+            price,
+            proceeds,
+            cost_basis,
+            realized_pnl: proceeds - cost_basis,
+            lots,
+        });
+        quantity
+    }
+
+    pub fn open_short(&mut self, id: String, symbol: &str, quantity: f64, price: f64, opened_at: DateTime<Utc>) {
+        if quantity <= 0.0 {
+            return;
+        }
+        self.lots.entry(symbol.to_string()).or_default().push(Lot { id, quantity: -quantity, cost: price, opened_at });
+    }
+
+    // Returns the quantity covered; nothing is recorded when it is zero
+    pub fn cover(
+        &mut self,
+        transaction_id: String,
+        symbol: &str,
+        quantity: f64,
+        price: f64,
+        lot_ids: &[String],
+        closed_at: DateTime<Utc>,
+    ) -> f64 {
+        let lots = self.relieve_side(symbol, quantity, lot_ids, true);
+        let quantity: f64 = lots.iter().map(|l| l.quantity).sum();
+        if quantity == 0.0 {
+            return 0.0;
+        }
+        let proceeds: f64 = lots.iter().map(|l| l.quantity * l.cost).sum();
+        let cost_basis = quantity * price;
+        self.realized.push(RealizedTrade {
+            transaction_id,
+            symbol: symbol.to_string(),
+            closed_at,
+            quantity,
+            price,
+            proceeds,
+            cost_basis,
+            realized_pnl: proceeds - cost_basis,
+            lots,
+        });
+        quantity
+    }
+
+    // Relieves long lots without recording a sale, e.g. for a transfer out
+    pub fn relieve(&mut self, symbol: &str, quantity: f64, lot_ids: &[String]) -> Vec<LotClosing> {
+        self.relieve_side(symbol, quantity, lot_ids, false)
+    }
+
+    fn relieve_side(&mut self, symbol: &str, quantity: f64, lot_ids: &[String], short: bool) -> Vec<LotClosing> {
+        let mut remaining = quantity.max(0.0);
+        let method = self.method;
+        let mut closings = Vec::new();
+        let Some(lots) = self.lots.get_mut(symbol) else {
+            return closings;
+        };
+        let side: Vec<usize> = (0..lots.len()).filter(|i| (lots[*i].quantity < 0.0) == short).collect();
+        let side_quantity: f64 = side.iter().map(|i| lots[*i].quantity.abs()).sum();
+        let average_cost = side.iter().map(|i| lots[*i].quantity.abs() * lots[*i].cost).sum::<f64>() / side_quantity;
+
+        for index in relief_order(lots, &side, method, lot_ids) {
+            if remaining <= 0.0 {
+                break;
+            }
+            let lot = &mut lots[index];
+            let relieved = lot.quantity.abs().min(remaining);
+            lot.quantity += if short { relieved } else { -relieved };
+            remaining -= relieved;
+            closings.push(LotClosing {
+                lot_id: lot.id.clone(),
+                quantity: relieved,
+                cost: if method == ReliefMethod::AverageCost { average_cost } else { lot.cost },
+                opened_at: lot.opened_at,
+            });
+        }
+        lots.retain(|l| l.quantity.abs() > f64::EPSILON);
+        if method == ReliefMethod::AverageCost {
+            // Units left keep the average cost, so the open cost basis is unchanged per unit
+            for lot in lots.iter_mut().filter(|l| (l.quantity < 0.0) == short) {
+                lot.cost = average_cost;
+            }
+        }
+        if lots.is_empty() {
+            self.lots.remove(symbol);
+        }
+        closings
+    }
+
+    // Replaces the symbol's lots with a single lot, as an adjustment does
+    pub fn reset(&mut self, id: String, symbol: &str, quantity: f64, cost: f64, opened_at: DateTime<Utc>) {
+        self.lots.remove(symbol);
+        self.open(id, symbol, quantity, cost, opened_at);
+    }
+
+    // Keeps the cost basis of each lot
+    pub fn split(&mut self, symbol: &str, ratio: f64) -> f64 {
+        if ratio <= 0.0 {
+            return 0.0;
+        }
+        let before = self.quantity(symbol);
+        for lot in self.lots.get_mut(symbol).into_iter().flatten() {
+            lot.quantity *= ratio;
+            lot.cost /= ratio;
+        }
+        self.quantity(symbol) - before
+    }
+
+    // Lots keep their IDs, cost and acquisition dates, after any already held in `new_symbol`
+    pub fn rename(&mut self, symbol: &str, new_symbol: &str) -> Vec<Lot> {
+        let Some(lots) = self.lots.remove(symbol) else {
+            return Vec::new();
+        };
+        self.lots.entry(new_symbol.to_string()).or_default().extend(lots.iter().cloned());
+        lots
+    }
+
+    // Moves `cost_allocation` (0 to 1) of each lot's cost basis to the new lot
+    pub fn spin_off(&mut self, symbol: &str, new_symbol: &str, ratio: f64, cost_allocation: f64) -> f64 {
+        if ratio <= 0.0 {
+            return 0.0;
+        }
+        let allocation = cost_allocation.clamp(0.0, 1.0);
+        let mut spun_off = Vec::new();
+        for lot in self.lots.get_mut(symbol).into_iter().flatten() {
+            let moved = lot.cost * allocation;
+            lot.cost -= moved;
+            spun_off.push(Lot {
+                id: format!("{}:{}", lot.id, new_symbol),
+                quantity: lot.quantity * ratio,
+                cost: moved / ratio,
+                opened_at: lot.opened_at,
+            });
+        }
+        let quantity = spun_off.iter().map(|l| l.quantity).sum();
+        if !spun_off.is_empty() {
+            self.lots.entry(new_symbol.to_string()).or_default().extend(spun_off);
+        }
+        quantity
+    }
+
+    // Open lots of `symbol` in acquisition order
+    pub fn lots(&self, symbol: &str) -> &[Lot] {
+        self.lots.get(symbol).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn quantity(&self, symbol: &str) -> f64 {
+        self.lots(symbol).iter().map(|l| l.quantity).sum()
+    }
+
+    // Quantity-weighted cost per unit, `None` without open lots
+    pub fn average_cost(&self, symbol: &str) -> Option<f64> {
+        let quantity = self.quantity(symbol);
+        if quantity.abs() <= f64::EPSILON {
+            return None;
+        }
+        Some(self.lots(symbol).iter().map(Lot::cost_basis).sum::<f64>() / quantity)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &String> {
+        self.lots.keys()
+    }
+
+    pub fn realized(&self) -> &[RealizedTrade] {
+        &self.realized
+    }
+
+    pub fn realized_pnl(&self, symbol: Option<&str>) -> f64 {
+        self.realized
+            .iter()
+            .filter(|t| symbol.is_none_or(|s| t.symbol == s))
+            .map(|t| t.realized_pnl)
+            .sum()
+    }
+
+    pub fn into_lots(self) -> HashMap<String, Vec<Lot>> {
+        self.lots
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportingPeriod {
+    Day,
+    #[default]
+    Month,
+    Quarter,
+    Year,
+}
+
+impl ReportingPeriod {
+    // e.g. `2025-08`, `2025-Q3` or `2025`
+    pub fn label(&self, date: NaiveDate) -> String {
+        match self {
+            ReportingPeriod::Day => date.to_string(),
+            ReportingPeriod::Month => format!("{}-{:02}", date.year(), date.month()),
+            ReportingPeriod::Quarter => format!("{}-Q{}", date.year(), (date.month() - 1) / 3 + 1),
+            ReportingPeriod::Year => date.year().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RealizedPnlRow {
+    // Symbol or period label
+    pub key: String,
+    pub trades: usize,
+    pub quantity: f64,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+}
+
+impl RealizedPnlRow {
+    fn add(&mut self, trade: &RealizedTrade) {
+        self.trades += 1;
+        self.quantity += trade.quantity;
+        self.proceeds += trade.proceeds;
+        self.cost_basis += trade.cost_basis;
+        self.realized_pnl += trade.realized_pnl;
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RealizedPnlReport {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub period: ReportingPeriod,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+    // Sorted by symbol
+    pub by_symbol: Vec<RealizedPnlRow>,
+    // In chronological order
+    pub by_period: Vec<RealizedPnlRow>,
+    pub trades: Vec<RealizedTrade>,
+}
+
+// Sales closed between `from` and `to` (inclusive), in total, per symbol and per period
+pub fn realized_pnl_report(
+    trades: &[RealizedTrade],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    period: ReportingPeriod,
+) -> RealizedPnlReport {
+    let selected: Vec<RealizedTrade> = trades
+        .iter()
+        .filter(|t| {
+            let date = t.closed_at.date_naive();
+            from.is_none_or(|f| date >= f) && to.is_none_or(|t| date <= t)
+        })
+        .cloned()
+        .collect();
+
+    let mut total = RealizedPnlRow::default();
+    let mut by_symbol: BTreeMap<String, RealizedPnlRow> = BTreeMap::new();
+    // Labels sort chronologically
+    let mut by_period: BTreeMap<String, RealizedPnlRow> = BTreeMap::new();
+    for trade in &selected {
+        total.add(trade);
+        by_symbol
+            .entry(trade.symbol.clone())
+            .or_insert_with(|| RealizedPnlRow { key: trade.symbol.clone(), ..Default::default() })
+            .add(trade);
+        let label = period.label(trade.closed_at.date_naive());
+        by_period
+            .entry(label.clone())
+            .or_insert_with(|| RealizedPnlRow { key: label, ..Default::default() })
+            .add(trade);
+    }
+
+    RealizedPnlReport {
+        from,
+        to,
+        period,
+        proceeds: total.proceeds,
+        cost_basis: total.cost_basis,
+        realized_pnl: total.realized_pnl,
+        by_symbol: by_symbol.into_values().collect(),
+        by_period: by_period.into_values().collect(),
+        trades: selected,
+    }
+}
+
+// Indices of the `side` lots (in acquisition order) in the order a sale closes them
+fn relief_order(lots: &[Lot], side: &[usize], method: ReliefMethod, lot_ids: &[String]) -> Vec<usize> {
+    let mut order: Vec<usize> = Vec::with_capacity(side.len());
+    if method != ReliefMethod::AverageCost {
+        for id in lot_ids {
+            if let Some(index) = side.iter().copied().find(|i| &lots[*i].id == id) {
+                if !order.contains(&index) {
+                    order.push(index);
+                }
+            }
+        }
+    }
+    let mut rest: Vec<usize> = side.iter().copied().filter(|i| !order.contains(i)).collect();
+    match method {
+        ReliefMethod::Lifo => rest.reverse(),
+        // Stable, so equal costs close oldest first
+        ReliefMethod::Hifo => rest.sort_by(|a, b| lots[*b].cost.total_cmp(&lots[*a].cost)),
+        ReliefMethod::Fifo | ReliefMethod::AverageCost | ReliefMethod::SpecificLot => {}
+    }
+    order.extend(rest);
+    order
+}
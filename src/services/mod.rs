@@ -1,7 +1,35 @@
+pub mod attribution;
+pub mod backtest;
+pub mod benchmark;
+pub mod corporate_actions;
+pub mod covariance;
+pub mod history;
+pub mod ledger;
+pub mod lots;
 pub mod market_data;
+pub mod optimization;
+pub mod performance;
 pub mod portfolio;
+pub mod rebalance;
 pub mod risk;
+pub mod scenario;
+pub mod scope;
+pub mod snapshot;
 
+pub use attribution::*;
+pub use backtest::*;
+pub use benchmark::*;
+pub use corporate_actions::*;
+pub use covariance::*;
+pub use history::*;
+pub use ledger::*;
+pub use lots::*;
 pub use market_data::*;
+pub use optimization::*;
+pub use performance::*;
 pub use portfolio::*;
+pub use rebalance::*;
 pub use risk::*;
+pub use scenario::*;
+pub use scope::*;
+pub use snapshot::*;
@@ -0,0 +1,627 @@
+use crate::{
+    repair_covariance, Instrument, MarketContext, Portfolio, Result, ReturnSeries, RiskEngine, Stock, ValuationError,
+    Valuator,
+};
+use nalgebra as na;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+const ADMM_MAX_ITERATIONS: usize = 50_000;
+const ADMM_TOLERANCE: f64 = 1e-9;
+// Residual below which an unconverged solve is still accepted
+const ADMM_ACCEPTABLE_RESIDUAL: f64 = 1e-6;
+const GOLDEN_SECTION_ITERATIONS: usize = 48;
+const RISK_PARITY_MAX_ITERATIONS: usize = 100;
+const FEASIBILITY_TOLERANCE: f64 = 1e-7;
+// Per-asset weight bounds when short positions are allowed and no bound is given
+const DEFAULT_SHORT_BOUND: f64 = 1.0;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum OptimizationObjective {
+    #[default]
+    MinimumVariance,
+    MaximumSharpe,
+    TargetReturn { target_return: f64 },
+    // Equal risk contributions
+    RiskParity,
+    // Maximizes weighted average volatility over portfolio volatility
+    MaximumDiversification,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WeightBounds {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+// Constraints on the optimized weights, which always sum to one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizationConstraints {
+    #[serde(default = "default_long_only")]
+    pub long_only: bool,
+    pub min_weight: Option<f64>,
+    pub max_weight: Option<f64>,
+    // Per-symbol bounds, overriding `min_weight` and `max_weight`
+    #[serde(default)]
+    pub bounds: HashMap<String, WeightBounds>,
+    // Maximum total weight per sector
+    #[serde(default)]
+    pub sector_caps: HashMap<String, f64>,
+    pub max_turnover: Option<f64>,
+}
+
+fn default_long_only() -> bool {
+    true
+}
+
+impl Default for OptimizationConstraints {
+    fn default() -> Self {
+        Self {
+            long_only: true,
+            min_weight: None,
+            max_weight: None,
+            bounds: HashMap::new(),
+            sector_caps: HashMap::new(),
+            max_turnover: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizationAsset {
+    pub symbol: String,
+    pub expected_return: f64,
+    pub sector: Option<String>,
+    #[serde(default)]
+    pub current_weight: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizedWeight {
+    pub symbol: String,
+    pub sector: Option<String>,
+    pub weight: f64,
+    pub current_weight: f64,
+    pub change: f64,
+    // Share of portfolio variance
+    pub risk_contribution: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OptimizedPortfolio {
+    pub objective: OptimizationObjective,
+    pub weights: Vec<OptimizedWeight>,
+    pub expected_return: f64,
+    pub volatility: f64,
+    pub sharpe_ratio: Option<f64>,
+    pub diversification_ratio: f64,
+    // One-way turnover from the current weights
+    pub turnover: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FrontierPoint {
+    pub expected_return: f64,
+    pub volatility: f64,
+    pub sharpe_ratio: Option<f64>,
+    pub weights: BTreeMap<String, f64>,
+}
+
+// Mean-variance optimizer over an annualized covariance matrix
+pub struct PortfolioOptimizer {
+    assets: Vec<OptimizationAsset>,
+    covariance: na::DMatrix<f64>,
+    constraints: OptimizationConstraints,
+    risk_free_rate: f64,
+}
+
+impl PortfolioOptimizer {
+    // `covariance` is annualized, ordered like `assets`, and repaired if not positive definite
+    pub fn new(assets: Vec<OptimizationAsset>, covariance: na::DMatrix<f64>) -> Result<Self> {
+        let n = assets.len();
+        if n == 0 {
+            return Err(ValuationError::Portfolio("No assets to optimize".to_string()));
+        }
+        if covariance.nrows() != n || covariance.ncols() != n {
+            return Err(ValuationError::RiskCalculation(format!(
+                "Covariance is {}x{} for {} assets",
+                covariance.nrows(),
+                covariance.ncols(),
+                n
+            )));
+        }
+        if covariance.iter().any(|v| !v.is_finite()) || assets.iter().any(|a| !a.expected_return.is_finite()) {
+            return Err(ValuationError::RiskCalculation("Non-finite optimization inputs".to_string()));
+        }
+        Ok(Self {
+            assets,
+            covariance: repair_covariance(&((&covariance + covariance.transpose()) * 0.5)),
+            constraints: OptimizationConstraints::default(),
+            risk_free_rate: 0.0,
+        })
+    }
+
+    pub fn with_constraints(mut self, constraints: OptimizationConstraints) -> Self {
+        self.constraints = constraints;
+        self
+    }
+
+    pub fn with_risk_free_rate(mut self, risk_free_rate: f64) -> Self {
+        self.risk_free_rate = risk_free_rate;
+        self
+    }
+
+    pub fn optimize(&self, objective: &OptimizationObjective) -> Result<OptimizedPortfolio> {
+        let set = self.feasible_set()?;
+        let returns = self.expected_returns();
+        let weights = match objective {
+            OptimizationObjective::MinimumVariance => self.minimum_variance(&set)?,
+            OptimizationObjective::TargetReturn { target_return } => self
+                .solve(&set, &self.covariance, &zeros(self.assets.len()), Some((&returns, *target_return)))
+                .map_err(|_| {
+                    ValuationError::Portfolio(format!("Target return {} is not attainable", target_return))
+                })?,
+            OptimizationObjective::MaximumSharpe => {
+                let (_, high) = self.attainable_range(&set, &returns)?;
+                if high <= self.risk_free_rate {
+                    return Err(ValuationError::Portfolio(
+                        "No portfolio's expected return exceeds the risk-free rate".to_string(),
+                    ));
+                }
+                self.maximize_ratio(&set, &returns, self.risk_free_rate)?
+            }
+            OptimizationObjective::MaximumDiversification => {
+                let volatilities = self.covariance.diagonal().map(|v| v.max(0.0).sqrt());
+                self.maximize_ratio(&set, &volatilities, 0.0)?
+            }
+            OptimizationObjective::RiskParity => self.risk_parity(&set)?,
+        };
+        Ok(self.describe(objective.clone(), &weights))
+    }
+
+    // Target returns evenly spaced from the minimum-variance return to the highest attainable
+    pub fn efficient_frontier(&self, points: usize) -> Result<Vec<FrontierPoint>> {
+        let set = self.feasible_set()?;
+        let returns = self.expected_returns();
+        let (low, high) = self.attainable_range(&set, &returns)?;
+        let targets: Vec<f64> = match points {
+            0 => Vec::new(),
+            1 => vec![low],
+            _ => (0..points).map(|i| low + (high - low) * i as f64 / (points - 1) as f64).collect(),
+        };
+
+        let mut frontier: Vec<FrontierPoint> = Vec::with_capacity(targets.len());
+        for (i, target) in targets.into_iter().enumerate() {
+            let weights = if i == 0 {
+                self.minimum_variance(&set)?
+            } else {
+                self.solve(&set, &self.covariance, &zeros(self.assets.len()), Some((&returns, target)))?
+            };
+            let (expected_return, volatility) = (returns.dot(&weights), self.volatility(&weights));
+            frontier.push(FrontierPoint {
+                expected_return,
+                volatility,
+                sharpe_ratio: (volatility > 0.0).then(|| (expected_return - self.risk_free_rate) / volatility),
+                weights: self.assets.iter().zip(weights.iter()).map(|(a, w)| (a.symbol.clone(), *w)).collect(),
+            });
+        }
+        Ok(frontier)
+    }
+
+    fn expected_returns(&self) -> na::DVector<f64> {
+        na::DVector::from_iterator(self.assets.len(), self.assets.iter().map(|a| a.expected_return))
+    }
+
+    fn volatility(&self, weights: &na::DVector<f64>) -> f64 {
+        weights.dot(&(&self.covariance * weights)).max(0.0).sqrt()
+    }
+
+    fn minimum_variance(&self, set: &FeasibleSet) -> Result<na::DVector<f64>> {
+        self.solve(set, &self.covariance, &zeros(self.assets.len()), None)
+    }
+
+    // Lowest (at the minimum-variance portfolio) and highest attainable values of `a'w`
+    fn attainable_range(&self, set: &FeasibleSet, a: &na::DVector<f64>) -> Result<(f64, f64)> {
+        let low = a.dot(&self.minimum_variance(set)?);
+        // A small variance term keeps the linear program bounded and well conditioned
+        let regularization = &self.covariance * 1e-6;
+        let high = a.dot(&self.solve(set, &regularization, &(-a), None)?);
+        Ok((low, high.max(low)))
+    }
+
+    // Golden-section search for the max of (a'w - c) / volatility along the minimum-variance curve, where it is quasi-concave
+    fn maximize_ratio(&self, set: &FeasibleSet, a: &na::DVector<f64>, c: f64) -> Result<na::DVector<f64>> {
+        let (low, high) = self.attainable_range(set, a)?;
+        let zero = zeros(self.assets.len());
+        let evaluate = |level: f64| -> Result<(f64, na::DVector<f64>)> {
+            let weights = self.solve(set, &self.covariance, &zero, Some((a, level)))?;
+            let volatility = self.volatility(&weights);
+            let ratio = if volatility > 0.0 { (a.dot(&weights) - c) / volatility } else { f64::NEG_INFINITY };
+            Ok((ratio, weights))
+        };
+
+        let golden = (5.0f64.sqrt() - 1.0) / 2.0;
+        let (mut lo, mut hi) = (low.max(c.min(high)), high);
+        let mut x1 = hi - golden * (hi - lo);
+        let mut x2 = lo + golden * (hi - lo);
+        let (mut f1, mut f2) = (evaluate(x1)?, evaluate(x2)?);
+        for _ in 0..GOLDEN_SECTION_ITERATIONS {
+            if hi - lo < 1e-10 {
+                break;
+            }
+            if f1.0 < f2.0 {
+                lo = x1;
+                x1 = x2;
+                f1 = f2;
+                x2 = lo + golden * (hi - lo);
+                f2 = evaluate(x2)?;
+            } else {
+                hi = x2;
+                x2 = x1;
+                f2 = f1;
+                x1 = hi - golden * (hi - lo);
+                f1 = evaluate(x1)?;
+            }
+        }
+        Ok(if f1.0 >= f2.0 { f1.1 } else { f2.1 })
+    }
+
+    // Equal risk contributions (Spinu's convex formulation)
+    fn risk_parity(&self, set: &FeasibleSet) -> Result<na::DVector<f64>> {
+        if set.lower.iter().any(|l| *l < 0.0) {
+            return Err(ValuationError::Configuration("Risk parity requires long-only weights".to_string()));
+        }
+        let n = self.assets.len();
+        let budget = 1.0 / n as f64;
+        let sigma = &self.covariance;
+
+        // Newton's method on 1/2 y'Sy - b sum(ln y), then w = y / sum(y)
+        let mut y = sigma.diagonal().map(|v| 1.0 / v.max(1e-12).sqrt());
+        let objective = |y: &na::DVector<f64>| 0.5 * y.dot(&(sigma * y)) - budget * y.iter().map(|v| v.ln()).sum::<f64>();
+        for _ in 0..RISK_PARITY_MAX_ITERATIONS {
+            let gradient = sigma * &y - y.map(|v| budget / v);
+            if gradient.amax() < 1e-12 {
+                break;
+            }
+            let hessian = sigma + na::DMatrix::from_diagonal(&y.map(|v| budget / (v * v)));
+            let step = hessian
+                .cholesky()
+                .ok_or_else(|| ValuationError::RiskCalculation("Risk parity Hessian is singular".to_string()))?
+                .solve(&(-&gradient));
+            let current = objective(&y);
+            let mut scale = 1.0;
+            loop {
+                let candidate = &y + &step * scale;
+                if candidate.iter().all(|v| *v > 0.0) && objective(&candidate) <= current {
+                    y = candidate;
+                    break;
+                }
+                scale *= 0.5;
+                if scale < 1e-12 {
+                    break;
+                }
+            }
+        }
+        let weights = &y / y.sum();
+        if set.contains(&weights) {
+            return Ok(weights);
+        }
+
+        // Constrained: minimize 1/2 w'Sw - lambda b sum(ln w) over the feasible set, with lambda
+        // the variance of the unconstrained solution so both problems agree when it is feasible
+        let lambda = weights.dot(&(sigma * &weights));
+        let barrier = |w: &na::DVector<f64>| {
+            if w.iter().any(|v| *v <= 0.0) {
+                f64::INFINITY
+            } else {
+                0.5 * w.dot(&(sigma * w)) - lambda * budget * w.iter().map(|v| v.ln()).sum::<f64>()
+            }
+        };
+        let mut w = self.solve(set, sigma, &zeros(n), None)?;
+        // Start strictly inside the positive orthant
+        let interior = self.solve(set, &na::DMatrix::identity(n, n), &zeros(n), None)?;
+        w = w * 0.5 + interior * 0.5;
+        if w.iter().any(|v| *v <= 0.0) {
+            return Err(ValuationError::Portfolio(
+                "Constraints force a zero weight; risk parity needs every asset held".to_string(),
+            ));
+        }
+        for _ in 0..RISK_PARITY_MAX_ITERATIONS {
+            let curvature = w.map(|v| lambda * budget / (v * v));
+            let hessian = sigma + na::DMatrix::from_diagonal(&curvature);
+            let linear = w.map(|v| -2.0 * lambda * budget / v);
+            let target = self.solve(set, &hessian, &linear, None)?;
+            let direction = &target - &w;
+            if direction.amax() < 1e-10 {
+                break;
+            }
+            let current = barrier(&w);
+            let mut scale = 1.0;
+            while scale > 1e-12 && barrier(&(&w + &direction * scale)) > current {
+                scale *= 0.5;
+            }
+            w += direction * scale;
+        }
+        Ok(w)
+    }
+
+    fn describe(&self, objective: OptimizationObjective, weights: &na::DVector<f64>) -> OptimizedPortfolio {
+        let marginal = &self.covariance * weights;
+        let variance = weights.dot(&marginal);
+        let volatility = variance.max(0.0).sqrt();
+        let expected_return = self.expected_returns().dot(weights);
+        let weighted_volatility: f64 = weights
+            .iter()
+            .zip(self.covariance.diagonal().iter())
+            .map(|(w, v)| w * v.max(0.0).sqrt())
+            .sum();
+
+        let rows: Vec<OptimizedWeight> = self
+            .assets
+            .iter()
+            .enumerate()
+            .map(|(i, asset)| OptimizedWeight {
+                symbol: asset.symbol.clone(),
+                sector: asset.sector.clone(),
+                weight: weights[i],
+                current_weight: asset.current_weight,
+                change: weights[i] - asset.current_weight,
+                risk_contribution: if variance > 0.0 { weights[i] * marginal[i] / variance } else { 0.0 },
+            })
+            .collect();
+        let turnover = rows.iter().map(|r| r.change.abs()).sum::<f64>() / 2.0;
+
+        OptimizedPortfolio {
+            objective,
+            weights: rows,
+            expected_return,
+            volatility,
+            sharpe_ratio: (volatility > 0.0).then(|| (expected_return - self.risk_free_rate) / volatility),
+            diversification_ratio: if volatility > 0.0 { weighted_volatility / volatility } else { 0.0 },
+            turnover,
+        }
+    }
+
+    fn feasible_set(&self) -> Result<FeasibleSet> {
+        let c = &self.constraints;
+        let (default_lower, default_upper) = if c.long_only { (0.0, 1.0) } else { (-DEFAULT_SHORT_BOUND, DEFAULT_SHORT_BOUND) };
+        let mut lower = Vec::with_capacity(self.assets.len());
+        let mut upper = Vec::with_capacity(self.assets.len());
+        for asset in &self.assets {
+            let bounds = c.bounds.get(&asset.symbol);
+            let mut l = bounds.and_then(|b| b.min).or(c.min_weight).unwrap_or(default_lower);
+            let u = bounds.and_then(|b| b.max).or(c.max_weight).unwrap_or(default_upper);
+            if c.long_only {
+                l = l.max(0.0);
+            }
+            if !(l.is_finite() && u.is_finite()) || l > u {
+                return Err(ValuationError::Configuration(format!("Invalid weight bounds for {}", asset.symbol)));
+            }
+            lower.push(l);
+            upper.push(u);
+        }
+        if lower.iter().sum::<f64>() > 1.0 + FEASIBILITY_TOLERANCE || upper.iter().sum::<f64>() < 1.0 - FEASIBILITY_TOLERANCE {
+            return Err(ValuationError::Configuration("Weight bounds cannot sum to one".to_string()));
+        }
+
+        let mut sectors = Vec::new();
+        for (sector, cap) in &c.sector_caps {
+            if !cap.is_finite() || *cap < 0.0 {
+                return Err(ValuationError::Configuration(format!("Invalid cap for sector {}", sector)));
+            }
+            let members: Vec<usize> = (0..self.assets.len())
+                .filter(|i| self.assets[*i].sector.as_deref() == Some(sector.as_str()))
+                .collect();
+            if !members.is_empty() {
+                sectors.push((members, *cap));
+            }
+        }
+
+        let turnover = match c.max_turnover {
+            Some(limit) if !limit.is_finite() || limit < 0.0 => {
+                return Err(ValuationError::Configuration("Turnover limit must be non-negative".to_string()));
+            }
+            Some(limit) => Some((self.assets.iter().map(|a| a.current_weight).collect(), limit)),
+            None => None,
+        };
+        Ok(FeasibleSet { lower, upper, sectors, turnover })
+    }
+
+    // Minimizes 1/2 w'Pw + q'w over the feasible set, optionally with a'w = b
+    fn solve(
+        &self,
+        set: &FeasibleSet,
+        p: &na::DMatrix<f64>,
+        q: &na::DVector<f64>,
+        equality: Option<(&na::DVector<f64>, f64)>,
+    ) -> Result<na::DVector<f64>> {
+        let n = self.assets.len();
+        let m = if set.turnover.is_some() { 2 * n } else { n };
+        let mut rows: Vec<ConstraintRow> = Vec::new();
+        rows.push(((0..n).map(|i| (i, 1.0)).collect(), 1.0, 1.0));
+        if let Some((a, b)) = equality {
+            rows.push(((0..n).map(|i| (i, a[i])).collect(), b, b));
+        }
+        for i in 0..n {
+            rows.push((vec![(i, 1.0)], set.lower[i], set.upper[i]));
+        }
+        for (members, cap) in &set.sectors {
+            rows.push((members.iter().map(|i| (*i, 1.0)).collect(), f64::NEG_INFINITY, *cap));
+        }
+        if let Some((current, limit)) = &set.turnover {
+            // t_i >= |w_i - w0_i| and sum(t) <= 2 * limit
+            for (i, weight) in current.iter().enumerate() {
+                rows.push((vec![(n + i, 1.0)], 0.0, f64::INFINITY));
+                rows.push((vec![(i, 1.0), (n + i, -1.0)], f64::NEG_INFINITY, *weight));
+                rows.push((vec![(i, 1.0), (n + i, 1.0)], *weight, f64::INFINITY));
+            }
+            rows.push(((n..2 * n).map(|i| (i, 1.0)).collect(), f64::NEG_INFINITY, 2.0 * limit));
+        }
+
+        let mut a = na::DMatrix::zeros(rows.len(), m);
+        let mut l = na::DVector::zeros(rows.len());
+        let mut u = na::DVector::zeros(rows.len());
+        for (r, (entries, lo, hi)) in rows.iter().enumerate() {
+            for (c, v) in entries {
+                a[(r, *c)] = *v;
+            }
+            l[r] = *lo;
+            u[r] = *hi;
+        }
+        let mut p_full = na::DMatrix::zeros(m, m);
+        p_full.view_mut((0, 0), (n, n)).copy_from(p);
+        let mut q_full = na::DVector::zeros(m);
+        q_full.rows_mut(0, n).copy_from(q);
+
+        // Scale the objective to unit size; the minimizer is unchanged
+        let scale = p.iter().chain(q.iter()).fold(0.0f64, |acc, v| acc.max(v.abs()));
+        if scale > 0.0 {
+            p_full /= scale;
+            q_full /= scale;
+        }
+
+        let x = admm(&p_full, &q_full, &a, &l, &u)?;
+        let mut weights = x.rows(0, n).into_owned();
+        for i in 0..n {
+            weights[i] = weights[i].clamp(set.lower[i], set.upper[i]);
+        }
+        Ok(weights)
+    }
+}
+
+// Sparse row of the constraint matrix with its lower and upper limits
+type ConstraintRow = (Vec<(usize, f64)>, f64, f64);
+
+struct FeasibleSet {
+    lower: Vec<f64>,
+    upper: Vec<f64>,
+    sectors: Vec<(Vec<usize>, f64)>,
+    turnover: Option<(Vec<f64>, f64)>,
+}
+
+impl FeasibleSet {
+    fn contains(&self, weights: &na::DVector<f64>) -> bool {
+        let tolerance = FEASIBILITY_TOLERANCE;
+        let within_bounds = weights
+            .iter()
+            .enumerate()
+            .all(|(i, w)| *w >= self.lower[i] - tolerance && *w <= self.upper[i] + tolerance);
+        let within_caps = self
+            .sectors
+            .iter()
+            .all(|(members, cap)| members.iter().map(|i| weights[*i]).sum::<f64>() <= cap + tolerance);
+        let within_turnover = self.turnover.as_ref().is_none_or(|(current, limit)| {
+            weights.iter().zip(current).map(|(w, c)| (w - c).abs()).sum::<f64>() / 2.0 <= limit + tolerance
+        });
+        within_bounds && within_caps && within_turnover
+    }
+}
+
+fn zeros(n: usize) -> na::DVector<f64> {
+    na::DVector::zeros(n)
+}
+
+// Operator-splitting (OSQP-style ADMM) solver for min 1/2 x'Px + q'x s.t. l <= Ax <= u, with over-relaxation and adaptive step size
+fn admm(
+    p: &na::DMatrix<f64>,
+    q: &na::DVector<f64>,
+    a: &na::DMatrix<f64>,
+    l: &na::DVector<f64>,
+    u: &na::DVector<f64>,
+) -> Result<na::DVector<f64>> {
+    let (n, m) = (p.nrows(), a.nrows());
+    let (sigma, alpha) = (1e-6, 1.6);
+    let is_equality: Vec<bool> = (0..m).map(|i| (u[i] - l[i]).abs() < 1e-12).collect();
+    let rho_for = |rho: f64| na::DVector::from_iterator(m, is_equality.iter().map(|eq| if *eq { rho * 1e3 } else { rho }));
+    let factorize = |rho: &na::DVector<f64>| {
+        let kkt = p + na::DMatrix::identity(n, n) * sigma + a.transpose() * na::DMatrix::from_diagonal(rho) * a;
+        kkt.cholesky()
+            .ok_or_else(|| ValuationError::RiskCalculation("Optimization system is not positive definite".to_string()))
+    };
+
+    let mut rho_scalar = 0.1;
+    let mut rho = rho_for(rho_scalar);
+    let mut factor = factorize(&rho)?;
+    let mut x = na::DVector::zeros(n);
+    let mut z = na::DVector::zeros(m);
+    let mut y = na::DVector::zeros(m);
+    let a_t = a.transpose();
+    let (mut primal_residual, mut dual_residual) = (f64::INFINITY, f64::INFINITY);
+
+    for iteration in 1..=ADMM_MAX_ITERATIONS {
+        let rhs = &x * sigma - q + &a_t * (rho.component_mul(&z) - &y);
+        let x_tilde = factor.solve(&rhs);
+        let z_tilde = a * &x_tilde;
+        x = &x_tilde * alpha + &x * (1.0 - alpha);
+        let z_relaxed = &z_tilde * alpha + &z * (1.0 - alpha);
+        let z_next = na::DVector::from_iterator(
+            m,
+            (0..m).map(|i| (z_relaxed[i] + y[i] / rho[i]).clamp(l[i], u[i])),
+        );
+        y += rho.component_mul(&(&z_relaxed - &z_next));
+        z = z_next;
+
+        if iteration % 10 != 0 {
+            continue;
+        }
+        let ax = a * &x;
+        let px = p * &x;
+        let aty = &a_t * &y;
+        primal_residual = (&ax - &z).amax();
+        dual_residual = (&px + q + &aty).amax();
+        let primal_scale = ax.amax().max(z.amax());
+        let dual_scale = px.amax().max(aty.amax()).max(q.amax());
+        if primal_residual <= ADMM_TOLERANCE * (1.0 + primal_scale) && dual_residual <= ADMM_TOLERANCE * (1.0 + dual_scale) {
+            return Ok(x);
+        }
+
+        if iteration % 50 == 0 {
+            let ratio = ((primal_residual / primal_scale.max(1e-12)) / (dual_residual / dual_scale.max(1e-12)).max(1e-12)).sqrt();
+            let candidate = (rho_scalar * ratio).clamp(1e-6, 1e6);
+            if candidate > rho_scalar * 5.0 || candidate < rho_scalar / 5.0 {
+                rho_scalar = candidate;
+                rho = rho_for(rho_scalar);
+                factor = factorize(&rho)?;
+            }
+        }
+    }
+
+    if primal_residual <= ADMM_ACCEPTABLE_RESIDUAL && dual_residual <= ADMM_ACCEPTABLE_RESIDUAL {
+        Ok(x)
+    } else {
+        Err(ValuationError::Portfolio("Optimization constraints are infeasible".to_string()))
+    }
+}
+
+// Current weights are dollar-delta weights and expected returns the annualized mean daily returns of `history`
+pub fn optimization_assets(
+    engine: &RiskEngine,
+    portfolio: &Portfolio,
+    instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+    valuator: &dyn Valuator,
+    market_contexts: &HashMap<String, MarketContext>,
+    history: &ReturnSeries,
+) -> Result<Vec<OptimizationAsset>> {
+    let exposures = engine.underlying_exposures(portfolio, instruments, valuator, market_contexts, &history.symbols)?;
+    let total: f64 = exposures.iter().map(|e| e.dollar_delta).sum();
+    Ok(exposures
+        .iter()
+        .zip(&history.returns)
+        .map(|(exposure, returns)| {
+            let observed: Vec<f64> = returns.iter().copied().filter(|r| !r.is_nan()).collect();
+            let mean = if observed.is_empty() { 0.0 } else { observed.iter().sum::<f64>() / observed.len() as f64 };
+            let sector = instruments
+                .values()
+                .filter(|i| i.market_data_key() == exposure.symbol)
+                .find_map(|i| i.as_any().downcast_ref::<Stock>().and_then(|s| s.sector.clone()));
+            OptimizationAsset {
+                symbol: exposure.symbol.clone(),
+                expected_return: mean * TRADING_DAYS_PER_YEAR,
+                sector,
+                current_weight: if total != 0.0 { exposure.dollar_delta / total } else { 0.0 },
+            }
+        })
+        .collect())
+}
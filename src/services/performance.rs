@@ -0,0 +1,375 @@
+use crate::{BenchmarkStatistics, DailyCloses, PortfolioPerformance, Result, ValuationError};
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+const IRR_MAX_ITERATIONS: usize = 200;
+const IRR_TOLERANCE: f64 = 1e-10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerTrade {
+    pub date: NaiveDate,
+    pub symbol: String,
+    pub quantity: f64,
+    pub price: f64,
+}
+
+// A change of an account's cash balance from the ledger, positive into the account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CashMovement {
+    pub date: NaiveDate,
+    pub amount: f64,
+    pub external: bool,
+}
+
+// Net external flow: purchases are positive, sale proceeds taken out negative
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NavPoint {
+    pub date: NaiveDate,
+    pub value: f64,
+    pub net_flow: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodReturns {
+    pub mtd: Option<f64>,
+    pub qtd: Option<f64>,
+    pub ytd: Option<f64>,
+    pub inception: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerformanceReport {
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub start_value: f64,
+    pub end_value: f64,
+    pub net_flows: f64,
+    // Investment gain over the range: end value - start value - net flows
+    pub total_return: f64,
+    // Chain-linked daily returns
+    pub time_weighted_return: f64,
+    // Annualized internal rate of return of the flows (None if it does not converge)
+    pub money_weighted_return: Option<f64>,
+    pub annualized_return: f64,
+    pub volatility: Option<f64>,
+    pub sharpe_ratio: Option<f64>,
+    pub sortino_ratio: Option<f64>,
+    pub calmar_ratio: Option<f64>,
+    // Largest peak-to-trough decline of the time-weighted index, as a positive fraction
+    pub max_drawdown: f64,
+    pub periods: PeriodReturns,
+    // Comparison with the portfolio's benchmark over the same range, when one is available
+    #[serde(default)]
+    pub benchmark: Option<BenchmarkStatistics>,
+    pub daily_returns: Vec<(NaiveDate, f64)>,
+}
+
+// Holdings after each day's trades at the latest close on or before the day, or the last trade price
+pub fn build_nav_series(trades: &[LedgerTrade], closes: &DailyCloses, end: NaiveDate) -> Vec<NavPoint> {
+    let Some(inception) = trades.iter().map(|t| t.date).min() else {
+        return Vec::new();
+    };
+    let mut calendar: BTreeSet<NaiveDate> = trades.iter().map(|t| t.date).collect();
+    for series in closes.values() {
+        calendar.extend(series.range(inception..=end).map(|(d, _)| *d));
+    }
+
+    let mut trades_by_date: BTreeMap<NaiveDate, Vec<&LedgerTrade>> = BTreeMap::new();
+    for trade in trades {
+        trades_by_date.entry(trade.date).or_default().push(trade);
+    }
+
+    let mut holdings: HashMap<&str, f64> = HashMap::new();
+    let mut last_trade_price: HashMap<&str, f64> = HashMap::new();
+    let mut series = Vec::new();
+    for date in calendar.into_iter().filter(|d| *d <= end) {
+        let mut net_flow = 0.0;
+        for trade in trades_by_date.get(&date).into_iter().flatten() {
+            let held = holdings.entry(trade.symbol.as_str()).or_default();
+            // Sales are capped at the quantity held, as in the lot calculation
+            let quantity = if trade.quantity < 0.0 { trade.quantity.max(-*held) } else { trade.quantity };
+            *held += quantity;
+            net_flow += quantity * trade.price;
+            if trade.price > 0.0 {
+                last_trade_price.insert(trade.symbol.as_str(), trade.price);
+            }
+        }
+
+        let value = holdings_value(&holdings, closes, &last_trade_price, date);
+        series.push(NavPoint { date, value, net_flow });
+    }
+    series
+}
+
+// Securities as in `build_nav_series` plus cash
+pub fn build_account_nav_series(
+    trades: &[LedgerTrade],
+    cash: &[CashMovement],
+    closes: &DailyCloses,
+    end: NaiveDate,
+) -> Vec<NavPoint> {
+    let Some(inception) = trades.iter().map(|t| t.date).chain(cash.iter().map(|c| c.date)).min() else {
+        return Vec::new();
+    };
+    let mut calendar: BTreeSet<NaiveDate> = trades.iter().map(|t| t.date).chain(cash.iter().map(|c| c.date)).collect();
+    for series in closes.values() {
+        calendar.extend(series.range(inception..=end).map(|(d, _)| *d));
+    }
+
+    let mut trades_by_date: BTreeMap<NaiveDate, Vec<&LedgerTrade>> = BTreeMap::new();
+    for trade in trades {
+        trades_by_date.entry(trade.date).or_default().push(trade);
+    }
+    let mut cash_by_date: BTreeMap<NaiveDate, Vec<&CashMovement>> = BTreeMap::new();
+    for movement in cash {
+        cash_by_date.entry(movement.date).or_default().push(movement);
+    }
+
+    let mut holdings: HashMap<&str, f64> = HashMap::new();
+    let mut last_trade_price: HashMap<&str, f64> = HashMap::new();
+    let mut balance = 0.0;
+    let mut series = Vec::new();
+    for date in calendar.into_iter().filter(|d| *d <= end) {
+        for trade in trades_by_date.get(&date).into_iter().flatten() {
+            *holdings.entry(trade.symbol.as_str()).or_default() += trade.quantity;
+            balance -= trade.quantity * trade.price;
+            if trade.price > 0.0 {
+                last_trade_price.insert(trade.symbol.as_str(), trade.price);
+            }
+        }
+        let mut net_flow = 0.0;
+        for movement in cash_by_date.get(&date).into_iter().flatten() {
+            balance += movement.amount;
+            if movement.external {
+                net_flow += movement.amount;
+            }
+        }
+
+        let value = holdings_value(&holdings, closes, &last_trade_price, date) + balance;
+        series.push(NavPoint { date, value, net_flow });
+    }
+    series
+}
+
+// Holdings at the latest close on or before `date`, falling back to the last trade price
+fn holdings_value(holdings: &HashMap<&str, f64>, closes: &DailyCloses, last_trade_price: &HashMap<&str, f64>, date: NaiveDate) -> f64 {
+    holdings
+        .iter()
+        .map(|(symbol, quantity)| {
+            let price = closes
+                .get(*symbol)
+                .and_then(|s| s.range(..=date).next_back().map(|(_, p)| *p))
+                .or_else(|| last_trade_price.get(symbol).copied())
+                .unwrap_or(0.0);
+            quantity * price
+        })
+        .sum()
+}
+
+pub struct PerformanceEngine {
+    risk_free_rate: f64,
+}
+
+impl PerformanceEngine {
+    pub fn new(risk_free_rate: f64) -> Self {
+        Self { risk_free_rate }
+    }
+
+    // Performance over (`from`, `to`] of a NAV series
+    pub fn analyze(&self, series: &[NavPoint], from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<PerformanceReport> {
+        let (first, last) = match (series.first(), series.last()) {
+            (Some(first), Some(last)) => (first.date, last.date),
+            _ => return Err(ValuationError::Portfolio("No portfolio history to analyze".to_string())),
+        };
+        let end = to.unwrap_or(last).min(last);
+        // The range starts after the close of `from`; from inception it includes the first day
+        let later_start = from.filter(|f| *f > first);
+        let start = later_start.unwrap_or(first);
+        if start > end {
+            return Err(ValuationError::Portfolio("Start date is after end date".to_string()));
+        }
+
+        let all_returns = daily_returns(series);
+        let start_value = match later_start {
+            Some(start) => series.iter().rev().find(|p| p.date <= start).map(|p| p.value).unwrap_or(0.0),
+            None => 0.0,
+        };
+        let in_range = |d: NaiveDate| (later_start.is_none() || d > start) && d <= end;
+        let returns: Vec<(NaiveDate, f64)> = all_returns.iter().copied().filter(|(d, _)| in_range(*d)).collect();
+        let points: Vec<&NavPoint> = series.iter().filter(|p| in_range(p.date)).collect();
+        let end_value = points.last().map(|p| p.value).unwrap_or(start_value);
+        let net_flows: f64 = points.iter().map(|p| p.net_flow).sum();
+
+        let time_weighted_return = compound(returns.iter().map(|(_, r)| *r));
+        let days = (end - start).num_days().max(1) as f64;
+        let annualized_return = (1.0 + time_weighted_return).powf(365.0 / days) - 1.0;
+
+        let values: Vec<f64> = returns.iter().map(|(_, r)| *r).collect();
+        let volatility = sample_std_dev(&values).map(|s| s * TRADING_DAYS_PER_YEAR.sqrt());
+        let excess_return = annualized_return - self.risk_free_rate;
+        let sharpe_ratio = volatility.filter(|v| *v > 0.0).map(|v| excess_return / v);
+        let daily_rf = self.risk_free_rate / TRADING_DAYS_PER_YEAR;
+        let downside_deviation = (!values.is_empty()).then(|| {
+            let squares: f64 = values.iter().map(|r| (r - daily_rf).min(0.0).powi(2)).sum();
+            (squares / values.len() as f64).sqrt() * TRADING_DAYS_PER_YEAR.sqrt()
+        });
+        let sortino_ratio = downside_deviation.filter(|d| *d > 0.0).map(|d| excess_return / d);
+        let max_drawdown = max_drawdown(&values);
+        let calmar_ratio = (max_drawdown > 0.0).then(|| annualized_return / max_drawdown);
+
+        // Money-weighted: invest the start value and every flow, receive the end value
+        let mut cash_flows = vec![(start, -start_value)];
+        cash_flows.extend(points.iter().map(|p| (p.date, -p.net_flow)));
+        cash_flows.push((end, end_value));
+        let money_weighted_return = xirr(&cash_flows);
+
+        Ok(PerformanceReport {
+            start_date: start,
+            end_date: end,
+            start_value,
+            end_value,
+            net_flows,
+            total_return: end_value - start_value - net_flows,
+            time_weighted_return,
+            money_weighted_return,
+            annualized_return,
+            volatility,
+            sharpe_ratio,
+            sortino_ratio,
+            calmar_ratio,
+            max_drawdown,
+            periods: period_returns(&all_returns, first, end),
+            benchmark: None,
+            daily_returns: returns,
+        })
+    }
+}
+
+impl Default for PerformanceEngine {
+    fn default() -> Self {
+        Self::new(0.0)
+    }
+}
+
+impl PortfolioPerformance {
+    // Fills the history-based fields from a performance report
+    pub fn with_history(mut self, report: &PerformanceReport) -> Self {
+        if let Some((_, last)) = report.daily_returns.last() {
+            self.daily_return = Some(report.end_value * last / (1.0 + last));
+            self.daily_return_percentage = Some(last * 100.0);
+        }
+        self.sharpe_ratio = report.sharpe_ratio;
+        self.max_drawdown = Some(report.max_drawdown);
+        self.volatility = report.volatility;
+        self
+    }
+}
+
+fn daily_returns(series: &[NavPoint]) -> Vec<(NaiveDate, f64)> {
+    let mut previous = 0.0;
+    series
+        .iter()
+        .map(|point| {
+            let invested = previous + point.net_flow;
+            let r = if invested > 0.0 { (point.value - invested) / invested } else { 0.0 };
+            previous = point.value;
+            (point.date, r)
+        })
+        .collect()
+}
+
+fn compound(returns: impl Iterator<Item = f64>) -> f64 {
+    returns.fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0
+}
+
+fn sample_std_dev(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+    Some(variance.sqrt())
+}
+
+fn max_drawdown(returns: &[f64]) -> f64 {
+    let (mut index, mut peak, mut worst) = (1.0f64, 1.0f64, 0.0f64);
+    for r in returns {
+        index *= 1.0 + r;
+        peak = peak.max(index);
+        worst = worst.max(1.0 - index / peak);
+    }
+    worst
+}
+
+// Month-, quarter- and year-to-date and since-inception returns ending at `end`
+fn period_returns(returns: &[(NaiveDate, f64)], inception: NaiveDate, end: NaiveDate) -> PeriodReturns {
+    let since = |boundary: Option<NaiveDate>| {
+        boundary.map(|b| compound(returns.iter().filter(|(d, _)| *d >= b && *d <= end).map(|(_, r)| *r)))
+    };
+    let quarter_month = (end.month0() / 3) * 3 + 1;
+    let starts = [
+        NaiveDate::from_ymd_opt(end.year(), end.month(), 1),
+        NaiveDate::from_ymd_opt(end.year(), quarter_month, 1),
+        NaiveDate::from_ymd_opt(end.year(), 1, 1),
+    ];
+    // Periods that began before inception are reported as since inception
+    let [mtd, qtd, ytd] = starts.map(|s| since(s.map(|s| s.max(inception))));
+    PeriodReturns {
+        mtd,
+        qtd,
+        ytd,
+        inception: since(Some(inception)),
+    }
+}
+
+// Annualized rate r solving sum(cf / (1 + r)^(t / 365)) = 0, by Newton's method with a bisection fallback
+pub fn xirr(cash_flows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let origin = cash_flows.iter().map(|(d, _)| *d).min()?;
+    let flows: Vec<(f64, f64)> = cash_flows
+        .iter()
+        .filter(|(_, cf)| *cf != 0.0)
+        .map(|(d, cf)| ((*d - origin).num_days() as f64 / 365.0, *cf))
+        .collect();
+    if !(flows.iter().any(|(_, cf)| *cf > 0.0) && flows.iter().any(|(_, cf)| *cf < 0.0)) {
+        return None;
+    }
+    let npv = |rate: f64| flows.iter().map(|(t, cf)| cf / (1.0 + rate).powf(*t)).sum::<f64>();
+    let derivative = |rate: f64| flows.iter().map(|(t, cf)| -t * cf / (1.0 + rate).powf(t + 1.0)).sum::<f64>();
+
+    let mut rate = 0.1;
+    for _ in 0..IRR_MAX_ITERATIONS {
+        let (value, slope) = (npv(rate), derivative(rate));
+        if value.abs() < IRR_TOLERANCE {
+            return Some(rate);
+        }
+        if slope == 0.0 || !slope.is_finite() {
+            break;
+        }
+        let next = rate - value / slope;
+        if !next.is_finite() || next <= -1.0 {
+            break;
+        }
+        rate = next;
+    }
+
+    // Bisection over (-0.9999, 100)
+    let (mut low, mut high) = (-0.9999, 100.0);
+    if npv(low).signum() == npv(high).signum() {
+        return None;
+    }
+    for _ in 0..IRR_MAX_ITERATIONS {
+        let mid = (low + high) / 2.0;
+        let value = npv(mid);
+        if value.abs() < IRR_TOLERANCE || (high - low) < IRR_TOLERANCE {
+            return Some(mid);
+        }
+        if value.signum() == npv(low).signum() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some((low + high) / 2.0)
+}
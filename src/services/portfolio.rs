@@ -1,4 +1,9 @@
-use crate::{Instrument, MarketContext, Result, RiskEngine, RiskMetrics, ValuationError, ValuationResult, Valuator};
+use crate::{
+    brinson_fachler, segment_performance, AttributionGrouping, CovarianceEstimator, Instrument,
+    InstrumentClassification, MarketContext, PeriodAttribution, Result, ReturnSeries, RiskEngine, RiskMetrics,
+    Scenario, ScenarioValuation, SegmentPerformance, ValuationError, ValuationFailure, ValuationResult, Valuator,
+};
+use nalgebra as na;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -30,8 +35,44 @@ pub struct PortfolioValuation {
     pub currency: String,
     pub positions: Vec<PositionValuation>,
     pub risk_metrics: Option<RiskMetrics>,
+    #[serde(default)]
+    pub risk_decomposition: Option<PortfolioRiskDecomposition>,
     pub timestamp: DateTime<Utc>,
     pub performance: Option<PortfolioPerformance>,
+    // Positions left out of the totals because they could not be valued
+    #[serde(default)]
+    pub errors: Vec<ValuationFailure>,
+    // P&L of the valued positions against their cost
+    #[serde(default)]
+    pub unrealized_pnl: f64,
+    // P&L closed by sales, when the caller tracks them (see `with_realized_pnl`)
+    #[serde(default)]
+    pub realized_pnl: Option<f64>,
+    // Realized plus unrealized P&L
+    #[serde(default)]
+    pub total_pnl: Option<f64>,
+    // Cash balance of the account, when the caller tracks one (see `with_cash`)
+    #[serde(default)]
+    pub cash: Option<f64>,
+    // Positions plus cash
+    #[serde(default)]
+    pub account_value: Option<f64>,
+}
+
+impl PortfolioValuation {
+    // Adds the P&L realized by past sales to the valuation's total P&L
+    pub fn with_realized_pnl(mut self, realized_pnl: f64) -> Self {
+        self.realized_pnl = Some(realized_pnl);
+        self.total_pnl = Some(realized_pnl + self.unrealized_pnl);
+        self
+    }
+
+    // Adds the account's cash balance; `total_value` stays the value of the positions
+    pub fn with_cash(mut self, cash: f64) -> Self {
+        self.cash = Some(cash);
+        self.account_value = Some(self.total_value + cash);
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,6 +88,32 @@ pub struct PositionValuation {
     pub valuation_result: ValuationResult,
 }
 
+// Delta-normal portfolio VaR from the covariance of the holdings' underlyings, allocated to positions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortfolioRiskDecomposition {
+    pub var: f64,
+    pub expected_shortfall: f64,
+    // Annualized volatility of portfolio returns
+    pub volatility: f64,
+    pub confidence_level: f64,
+    pub horizon_days: i64,
+    pub positions: Vec<PositionRiskContribution>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionRiskContribution {
+    pub position_id: String,
+    pub instrument_id: String,
+    // Dollar delta to the position's underlying
+    pub exposure: f64,
+    // Change in portfolio VaR per dollar of additional exposure
+    pub marginal_var: f64,
+    pub component_var: f64,
+    // Portfolio VaR minus the VaR without this position
+    pub incremental_var: f64,
+    pub contribution_percentage: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PortfolioPerformance {
     pub total_return: f64,
@@ -117,11 +184,21 @@ impl Portfolio {
 
 pub struct PortfolioValuationService {
     risk_engine: RiskEngine,
+    covariance_estimator: CovarianceEstimator,
 }
 
 impl PortfolioValuationService {
     pub fn new(risk_engine: RiskEngine) -> Self {
-        Self { risk_engine }
+        Self {
+            risk_engine,
+            covariance_estimator: CovarianceEstimator::default(),
+        }
+    }
+
+    // Estimator used for the covariance of the holdings' underlying returns
+    pub fn with_covariance_estimator(mut self, covariance_estimator: CovarianceEstimator) -> Self {
+        self.covariance_estimator = covariance_estimator;
+        self
     }
 
     pub async fn value_portfolio(
@@ -131,115 +208,234 @@ impl PortfolioValuationService {
         valuator: &dyn Valuator,
         market_context: &MarketContext,
     ) -> Result<PortfolioValuation> {
-        let mut position_valuations = Vec::new();
-        let mut total_value = 0.0;
+        let (position_valuations, total_value) =
+            value_positions(portfolio, instruments, valuator, |_| Some(market_context.clone()))?;
+        Ok(self.assemble_valuation(portfolio, position_valuations, total_value))
+    }
 
-        // Value each position
-        for position in &portfolio.positions {
-            let instrument = instruments.get(&position.instrument_id)
-                .ok_or_else(|| ValuationError::Portfolio(
-                    format!("Instrument not found: {}", position.instrument_id)
-                ))?;
-
-            let valuation_result = valuator.value(instrument.as_ref(), market_context)?;
-            let unit_value = valuation_result.value / instrument.notional();
-            let position_total_value = unit_value * position.quantity;
-            
-            // Calculate P&L if we have average cost
-            let (pnl, pnl_percentage) = if let Some(avg_cost) = position.average_cost {
-                let total_cost = avg_cost * position.quantity;
-                let pnl = position_total_value - total_cost;
-                let pnl_pct = if total_cost != 0.0 { pnl / total_cost * 100.0 } else { 0.0 };
-                (Some(pnl), Some(pnl_pct))
-            } else {
-                (None, None)
-            };
-
-            position_valuations.push(PositionValuation {
-                position_id: position.id.clone(),
-                instrument_id: position.instrument_id.clone(),
-                quantity: position.quantity,
-                unit_value,
-                total_value: position_total_value,
-                weight: 0.0, // Will be calculated after total value is known
-                pnl,
-                pnl_percentage,
-                valuation_result,
-            });
+    // One market context per market data key, so each position is priced off its own underlying
+    pub async fn value_portfolio_with_contexts(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+    ) -> Result<PortfolioValuation> {
+        let (position_valuations, total_value) = value_positions(
+            portfolio,
+            instruments,
+            valuator,
+            |instrument| market_contexts.get(instrument.market_data_key()).cloned(),
+        )?;
+        Ok(self.assemble_valuation(portfolio, position_valuations, total_value))
+    }
 
-            total_value += position_total_value;
-        }
+    // Adds covariance-based risk metrics from the daily underlying returns in `history`
+    pub async fn value_portfolio_with_risk(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        history: &ReturnSeries,
+    ) -> Result<PortfolioValuation> {
+        let mut valuation = self
+            .value_portfolio_with_contexts(portfolio, instruments, valuator, market_contexts)
+            .await?;
+        let decomposition = self.calculate_portfolio_risk_metrics(
+            portfolio,
+            instruments,
+            valuator,
+            market_contexts,
+            history,
+            valuation.total_value,
+        )?;
+        attach_risk(&mut valuation, decomposition);
+        Ok(valuation)
+    }
 
-        // Calculate weights
-        for position_val in &mut position_valuations {
-            position_val.weight = if total_value != 0.0 {
-                position_val.total_value / total_value * 100.0
-            } else {
-                0.0
-            };
+    // Reports positions that cannot be priced in `errors` instead of failing
+    pub async fn value_portfolio_best_effort(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        history: Option<&ReturnSeries>,
+    ) -> PortfolioValuation {
+        let context_for = |instrument: &dyn Instrument| market_contexts.get(instrument.market_data_key()).cloned();
+        let mut valued = portfolio.clone();
+        valued.positions.clear();
+        let mut position_valuations = Vec::new();
+        let mut errors = Vec::new();
+        for position in &portfolio.positions {
+            match value_position(position, instruments, valuator, &context_for) {
+                Ok(valuation) => {
+                    position_valuations.push(valuation);
+                    valued.positions.push(position.clone());
+                }
+                Err(e) => errors.push(ValuationFailure { instrument_id: position.instrument_id.clone(), error: e.to_string() }),
+            }
+        }
+        let total_value = assign_weights(&mut position_valuations);
+
+        let mut valuation = self.assemble_valuation(portfolio, position_valuations, total_value);
+        valuation.errors = errors;
+        if let Some(history) = history {
+            if let Ok(decomposition) =
+                self.calculate_portfolio_risk_metrics(&valued, instruments, valuator, market_contexts, history, total_value)
+            {
+                attach_risk(&mut valuation, decomposition);
+            }
         }
+        valuation
+    }
 
-        // Calculate portfolio risk metrics
-        let risk_metrics = self.calculate_portfolio_risk_metrics(
-            &position_valuations,
-            total_value,
-            market_context,
-        ).ok();
+    // P&L of a full revaluation under each scenario against the unshifted valuation
+    pub async fn value_scenarios(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        scenarios: &[Scenario],
+    ) -> Result<Vec<ScenarioValuation>> {
+        let (base_positions, base_value) = value_positions(
+            portfolio,
+            instruments,
+            valuator,
+            |instrument| market_contexts.get(instrument.market_data_key()).cloned(),
+        )?;
+
+        let mut results = Vec::with_capacity(scenarios.len());
+        for scenario in scenarios {
+            let shifted = scenario.apply(market_contexts)?;
+            let (positions, scenario_value) = value_positions(
+                portfolio,
+                instruments,
+                valuator,
+                |instrument| shifted.get(instrument.market_data_key()).cloned(),
+            )?;
+            results.push(ScenarioValuation::from_revaluation(
+                &scenario.name,
+                &base_positions,
+                base_value,
+                &positions,
+                scenario_value,
+            ));
+        }
+        Ok(results)
+    }
 
+    fn assemble_valuation(
+        &self,
+        portfolio: &Portfolio,
+        position_valuations: Vec<PositionValuation>,
+        total_value: f64,
+    ) -> PortfolioValuation {
         // Calculate performance metrics
         let performance = self.calculate_portfolio_performance(&position_valuations);
+        let unrealized_pnl = position_valuations.iter().filter_map(|p| p.pnl).sum();
 
-        Ok(PortfolioValuation {
+        PortfolioValuation {
             portfolio_id: portfolio.id.clone(),
             total_value,
             currency: portfolio.base_currency.clone(),
             positions: position_valuations,
-            risk_metrics,
+            // Needs return history, see value_portfolio_with_risk
+            risk_metrics: None,
+            risk_decomposition: None,
             timestamp: Utc::now(),
             performance,
-        })
+            errors: Vec::new(),
+            unrealized_pnl,
+            realized_pnl: None,
+            total_pnl: None,
+            cash: None,
+            account_value: None,
+        }
     }
 
-    fn calculate_portfolio_risk_metrics(
+    // Delta-normal VaR from the daily underlying returns in `history`, decomposed per position
+    pub fn calculate_portfolio_risk_metrics(
         &self,
-        positions: &[PositionValuation],
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        history: &ReturnSeries,
         total_value: f64,
-        _market_context: &MarketContext,
-    ) -> Result<RiskMetrics> {
-        if positions.is_empty() || total_value == 0.0 {
-            return Ok(RiskMetrics {
-                var_1d: None,
-                var_10d: None,
-                expected_shortfall: None,
-                volatility: None,
-            });
+    ) -> Result<PortfolioRiskDecomposition> {
+        if portfolio.positions.is_empty() {
+            return Err(ValuationError::Portfolio("Portfolio has no positions".to_string()));
         }
+        let underlying_covariance = self.covariance_estimator.estimate(&history.returns)?;
 
-        // Calculate portfolio volatility as weighted average (simplified)
-        let mut portfolio_volatility = 0.0;
-        let mut total_weight = 0.0;
-
-        for position in positions {
-            if let Some(vol) = position.valuation_result.risk_metrics.as_ref()
-                .and_then(|rm| rm.volatility) {
-                let weight = position.total_value / total_value;
-                portfolio_volatility += weight * vol;
-                total_weight += weight;
+        let mut exposures = Vec::with_capacity(portfolio.positions.len());
+        let mut underlying_index = Vec::with_capacity(portfolio.positions.len());
+        for position in &portfolio.positions {
+            let instrument = instruments.get(&position.instrument_id)
+                .ok_or_else(|| ValuationError::Portfolio(format!("Instrument not found: {}", position.instrument_id)))?;
+            let key = instrument.market_data_key();
+            let context = market_contexts.get(key)
+                .ok_or_else(|| ValuationError::MarketData(format!("No market data for {}", key)))?;
+            let greeks = valuator.calculate_greeks(instrument.as_ref(), context)?;
+            let index = history.symbols.iter().position(|s| s == key);
+
+            match (greeks.delta, index) {
+                (None, None) => {
+                    exposures.push(0.0);
+                    underlying_index.push(None);
+                }
+                (None, Some(_)) => {
+                    return Err(ValuationError::RiskCalculation(format!("No delta available for {}", instrument.id())));
+                }
+                (Some(_), None) => {
+                    return Err(ValuationError::RiskCalculation(format!("No return history for {}", key)));
+                }
+                (Some(delta), Some(i)) => {
+                    exposures.push(delta * context.spot_price.unwrap_or(0.0) * position.quantity);
+                    underlying_index.push(Some(i));
+                }
             }
         }
 
-        if total_weight > 0.0 {
-            portfolio_volatility /= total_weight;
-        } else {
-            portfolio_volatility = 0.20; // Default 20% volatility
-        }
-
-        // Use risk engine to calculate portfolio risk metrics
-        self.risk_engine.calculate_portfolio_risk_metrics(
-            total_value,
-            portfolio_volatility,
-            0.08, // Assume 8% expected return
-        )
+        // Covariance of the positions' underlying returns
+        let n = exposures.len();
+        let covariance = na::DMatrix::from_fn(n, n, |a, b| match (underlying_index[a], underlying_index[b]) {
+            (Some(i), Some(j)) => underlying_covariance[(i, j)],
+            _ => 0.0,
+        });
+        let decomposition = self.risk_engine.decompose_var(&exposures, &covariance)?;
+
+        let positions = portfolio
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| PositionRiskContribution {
+                position_id: position.id.clone(),
+                instrument_id: position.instrument_id.clone(),
+                exposure: exposures[i],
+                marginal_var: decomposition.marginal[i],
+                component_var: decomposition.component[i],
+                incremental_var: decomposition.incremental[i],
+                contribution_percentage: if decomposition.var != 0.0 {
+                    decomposition.component[i] / decomposition.var * 100.0
+                } else {
+                    0.0
+                },
+            })
+            .collect();
+
+        let daily_std_dev = decomposition.std_dev / (decomposition.horizon_days.max(1) as f64).sqrt();
+        Ok(PortfolioRiskDecomposition {
+            var: decomposition.var,
+            expected_shortfall: decomposition.expected_shortfall,
+            volatility: if total_value != 0.0 { daily_std_dev / total_value.abs() * 252f64.sqrt() } else { 0.0 },
+            confidence_level: decomposition.confidence_level,
+            horizon_days: decomposition.horizon_days,
+            positions,
+        })
     }
 
     fn calculate_portfolio_performance(&self, positions: &[PositionValuation]) -> Option<PortfolioPerformance> {
@@ -277,63 +473,182 @@ impl PortfolioValuationService {
         })
     }
 
+    // Brinson-Fachler attribution between two valuations of the portfolio against benchmark segments
     pub fn calculate_portfolio_attribution(
         &self,
         current_valuation: &PortfolioValuation,
         previous_valuation: &PortfolioValuation,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        grouping: &AttributionGrouping,
+        classifications: &HashMap<String, InstrumentClassification>,
+        benchmark: &[SegmentPerformance],
     ) -> Result<PortfolioAttribution> {
-        let mut attributions = Vec::new();
+        if previous_valuation.total_value == 0.0 {
+            return Err(ValuationError::Portfolio("Previous valuation has no value to attribute".to_string()));
+        }
         let total_return = current_valuation.total_value - previous_valuation.total_value;
 
-        for current_pos in &current_valuation.positions {
-            if let Some(previous_pos) = previous_valuation.positions.iter()
-                .find(|p| p.instrument_id == current_pos.instrument_id) {
-                
-                let position_return = current_pos.total_value - previous_pos.total_value;
-                let contribution = if previous_valuation.total_value != 0.0 {
-                    position_return / previous_valuation.total_value * 100.0
-                } else {
-                    0.0
-                };
-
-                attributions.push(PositionAttribution {
-                    instrument_id: current_pos.instrument_id.clone(),
-                    contribution,
-                    position_return,
-                    weight_effect: 0.0, // Simplified - would need more complex calculation
-                    selection_effect: contribution, // Simplified
-                });
+        // Positions of the same instrument share a unit value
+        let mut previous_values: Vec<(&str, f64, f64)> = Vec::new();
+        for position in &previous_valuation.positions {
+            match previous_values.iter_mut().find(|(id, _, _)| *id == position.instrument_id) {
+                Some(entry) => entry.1 += position.total_value,
+                None => previous_values.push((&position.instrument_id, position.total_value, position.unit_value)),
             }
         }
 
+        let mut attributions = Vec::new();
+        for (instrument_id, previous_value, previous_unit) in previous_values {
+            let current_unit = current_valuation
+                .positions
+                .iter()
+                .find(|p| p.instrument_id == instrument_id)
+                .map(|p| p.unit_value)
+                .ok_or_else(|| ValuationError::Portfolio(format!("No current valuation for {}", instrument_id)))?;
+            let instrument = instruments
+                .get(instrument_id)
+                .ok_or_else(|| ValuationError::Portfolio(format!("Instrument not found: {}", instrument_id)))?;
+            let classification = classifications
+                .get(instrument.market_data_key())
+                .or_else(|| classifications.get(instrument_id));
+            let return_rate = if previous_unit != 0.0 { current_unit / previous_unit - 1.0 } else { 0.0 };
+            let weight = previous_value / previous_valuation.total_value;
+
+            attributions.push(PositionAttribution {
+                instrument_id: instrument_id.to_string(),
+                segment: grouping.segment_of(instrument.as_ref(), classification),
+                weight,
+                return_rate,
+                contribution: weight * return_rate * 100.0,
+                position_return: previous_value * return_rate,
+            });
+        }
+
+        let portfolio_segments =
+            segment_performance(attributions.iter().map(|a| (a.segment.clone(), a.weight, a.return_rate)));
+        let brinson = brinson_fachler(
+            previous_valuation.timestamp.date_naive(),
+            current_valuation.timestamp.date_naive(),
+            &portfolio_segments,
+            benchmark,
+        )?;
+
         Ok(PortfolioAttribution {
             total_return,
-            total_return_percentage: if previous_valuation.total_value != 0.0 {
-                total_return / previous_valuation.total_value * 100.0
-            } else {
-                0.0
-            },
+            total_return_percentage: total_return / previous_valuation.total_value * 100.0,
             position_attributions: attributions,
+            brinson,
             timestamp: Utc::now(),
         })
     }
 }
 
+pub(crate) fn value_positions<F>(
+    portfolio: &Portfolio,
+    instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+    valuator: &dyn Valuator,
+    context_for: F,
+) -> Result<(Vec<PositionValuation>, f64)>
+where
+    F: Fn(&dyn Instrument) -> Option<MarketContext>,
+{
+    let mut position_valuations = portfolio
+        .positions
+        .iter()
+        .map(|position| value_position(position, instruments, valuator, &context_for))
+        .collect::<Result<Vec<_>>>()?;
+    let total_value = assign_weights(&mut position_valuations);
+    Ok((position_valuations, total_value))
+}
+
+fn value_position<F>(
+    position: &Position,
+    instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+    valuator: &dyn Valuator,
+    context_for: &F,
+) -> Result<PositionValuation>
+where
+    F: Fn(&dyn Instrument) -> Option<MarketContext>,
+{
+    let instrument = instruments.get(&position.instrument_id)
+        .ok_or_else(|| ValuationError::Portfolio(
+            format!("Instrument not found: {}", position.instrument_id)
+        ))?;
+    let market_context = context_for(instrument.as_ref())
+        .ok_or_else(|| ValuationError::MarketData(
+            format!("No market data for {}", instrument.market_data_key())
+        ))?;
+
+    let valuation_result = valuator.value(instrument.as_ref(), &market_context)?;
+    let unit_value = valuation_result.value / instrument.notional();
+    let position_total_value = unit_value * position.quantity;
+
+    // Calculate P&L if we have average cost
+    let (pnl, pnl_percentage) = if let Some(avg_cost) = position.average_cost {
+        let total_cost = avg_cost * position.quantity;
+        let pnl = position_total_value - total_cost;
+        let pnl_pct = if total_cost != 0.0 { pnl / total_cost * 100.0 } else { 0.0 };
+        (Some(pnl), Some(pnl_pct))
+    } else {
+        (None, None)
+    };
+
+    Ok(PositionValuation {
+        position_id: position.id.clone(),
+        instrument_id: position.instrument_id.clone(),
+        quantity: position.quantity,
+        unit_value,
+        total_value: position_total_value,
+        weight: 0.0, // Will be calculated after total value is known
+        pnl,
+        pnl_percentage,
+        valuation_result,
+    })
+}
+
+// Sets each position's weight (percent of the total) and returns the total value
+fn assign_weights(position_valuations: &mut [PositionValuation]) -> f64 {
+    let total_value: f64 = position_valuations.iter().map(|p| p.total_value).sum();
+    for position_val in position_valuations.iter_mut() {
+        position_val.weight = if total_value != 0.0 {
+            position_val.total_value / total_value * 100.0
+        } else {
+            0.0
+        };
+    }
+    total_value
+}
+
+fn attach_risk(valuation: &mut PortfolioValuation, decomposition: PortfolioRiskDecomposition) {
+    // RiskMetrics are quoted per day
+    let horizon_scale = (decomposition.horizon_days.max(1) as f64).sqrt();
+    let var_1d = decomposition.var / horizon_scale;
+    valuation.risk_metrics = Some(RiskMetrics {
+        var_1d: Some(var_1d),
+        var_10d: Some(var_1d * 10f64.sqrt()),
+        expected_shortfall: Some(decomposition.expected_shortfall / horizon_scale),
+        volatility: Some(decomposition.volatility),
+    });
+    valuation.risk_decomposition = Some(decomposition);
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PortfolioAttribution {
     pub total_return: f64,
     pub total_return_percentage: f64,
     pub position_attributions: Vec<PositionAttribution>,
+    pub brinson: PeriodAttribution,
     pub timestamp: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PositionAttribution {
     pub instrument_id: String,
+    pub segment: String,
+    pub weight: f64,
+    pub return_rate: f64,
     pub contribution: f64, // Contribution to total portfolio return (%)
     pub position_return: f64,
-    pub weight_effect: f64,
-    pub selection_effect: f64,
 }
 
 impl Default for PortfolioValuationService {
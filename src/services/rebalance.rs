@@ -0,0 +1,403 @@
+use crate::{Result, ValuationError};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaxLot {
+    #[serde(default)]
+    pub id: String,
+    pub quantity: f64,
+    pub cost_basis: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebalanceHolding {
+    pub symbol: String,
+    pub price: f64,
+    // Open lots in acquisition order
+    pub lots: Vec<TaxLot>,
+}
+
+impl RebalanceHolding {
+    pub fn quantity(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LotSelection {
+    // Oldest lots first, as the ledger relieves sales by default
+    #[default]
+    Fifo,
+    // Highest-cost lots first, minimizing realized gains
+    MinimizeGains,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebalanceOptions {
+    // Share of total value kept in cash
+    #[serde(default)]
+    pub cash_buffer: f64,
+    // Trades smaller than this value are dropped
+    #[serde(default)]
+    pub min_trade_value: f64,
+    // Default trading increment; 0 allows fractional quantities
+    #[serde(default = "default_lot_size")]
+    pub lot_size: f64,
+    // Per-symbol trading increments
+    #[serde(default)]
+    pub lot_sizes: HashMap<String, f64>,
+    #[serde(default)]
+    pub lot_selection: LotSelection,
+}
+
+fn default_lot_size() -> f64 {
+    1.0
+}
+
+impl Default for RebalanceOptions {
+    fn default() -> Self {
+        Self {
+            cash_buffer: 0.0,
+            min_trade_value: 0.0,
+            lot_size: default_lot_size(),
+            lot_sizes: HashMap::new(),
+            lot_selection: LotSelection::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LotRelief {
+    // Index of the lot in the holding's acquisition order
+    pub lot_index: usize,
+    pub lot_id: String,
+    pub quantity: f64,
+    pub cost_basis: f64,
+    pub realized_gain: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposedTrade {
+    pub side: TradeSide,
+    pub symbol: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub value: f64,
+    // Lots relieved by a sale
+    pub lots: Vec<LotRelief>,
+    pub realized_gain: f64,
+}
+
+impl ProposedTrade {
+    // `lot_ids` that make the ledger relieve the same lots when the sale is posted
+    pub fn lot_ids(&self) -> Vec<String> {
+        self.lots.iter().map(|l| l.lot_id.clone()).filter(|id| !id.is_empty()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftRow {
+    pub symbol: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub value: f64,
+    pub current_weight: f64,
+    pub target_weight: f64,
+    // Current minus target weight
+    pub drift: f64,
+    pub drift_value: f64,
+    pub breached: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftReport {
+    pub total_value: f64,
+    pub cash: f64,
+    pub cash_weight: f64,
+    pub target_cash_weight: f64,
+    pub threshold: f64,
+    // Sum of absolute drifts divided by two
+    pub total_drift: f64,
+    pub needs_rebalance: bool,
+    pub rows: Vec<DriftRow>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RebalancePlan {
+    pub total_value: f64,
+    pub cash_before: f64,
+    pub cash_after: f64,
+    pub trades: Vec<ProposedTrade>,
+    pub buy_value: f64,
+    pub sell_value: f64,
+    pub realized_gain: f64,
+    // Drift after the proposed trades, measured with no threshold
+    pub drift_after: DriftReport,
+}
+
+pub struct RebalanceEngine {
+    options: RebalanceOptions,
+}
+
+impl RebalanceEngine {
+    pub fn new(options: RebalanceOptions) -> Self {
+        Self { options }
+    }
+
+    // Drift of the holdings from `targets` (weights summing to at most one; the rest is cash)
+    pub fn drift(
+        &self,
+        holdings: &[RebalanceHolding],
+        targets: &HashMap<String, f64>,
+        cash: f64,
+        threshold: f64,
+    ) -> Result<DriftReport> {
+        validate_targets(targets)?;
+        validate_holdings(holdings)?;
+        let total_value = holdings.iter().map(|h| h.quantity() * h.price).sum::<f64>() + cash;
+        let weight = |value: f64| if total_value > 0.0 { value / total_value } else { 0.0 };
+
+        let symbols: BTreeSet<&str> = holdings
+            .iter()
+            .filter(|h| h.quantity() > f64::EPSILON)
+            .map(|h| h.symbol.as_str())
+            .chain(targets.keys().map(String::as_str))
+            .collect();
+        let rows: Vec<DriftRow> = symbols
+            .into_iter()
+            .map(|symbol| {
+                let holding = holdings.iter().find(|h| h.symbol == symbol);
+                let quantity = holding.map_or(0.0, |h| h.quantity());
+                let price = holding.map_or(0.0, |h| h.price);
+                let value = quantity * price;
+                let target_weight = targets.get(symbol).copied().unwrap_or(0.0);
+                let drift = weight(value) - target_weight;
+                DriftRow {
+                    symbol: symbol.to_string(),
+                    quantity,
+                    price,
+                    value,
+                    current_weight: weight(value),
+                    target_weight,
+                    drift,
+                    drift_value: drift * total_value,
+                    breached: drift.abs() > threshold,
+                }
+            })
+            .collect();
+
+        let target_cash_weight = 1.0 - targets.values().sum::<f64>();
+        let cash_drift = weight(cash) - target_cash_weight;
+        let total_drift = (rows.iter().map(|r| r.drift.abs()).sum::<f64>() + cash_drift.abs()) / 2.0;
+        Ok(DriftReport {
+            total_value,
+            cash,
+            cash_weight: weight(cash),
+            target_cash_weight,
+            threshold,
+            total_drift,
+            needs_rebalance: rows.iter().any(|r| r.breached) || cash_drift.abs() > threshold,
+            rows,
+        })
+    }
+
+    // Trades moving the holdings to `targets` of the value left after the cash buffer
+    pub fn plan(&self, holdings: &[RebalanceHolding], targets: &HashMap<String, f64>, cash: f64) -> Result<RebalancePlan> {
+        validate_targets(targets)?;
+        validate_holdings(holdings)?;
+        let options = &self.options;
+        if !(0.0..1.0).contains(&options.cash_buffer) {
+            return Err(ValuationError::Configuration("Cash buffer must be in [0, 1)".to_string()));
+        }
+        if cash < 0.0 || options.min_trade_value < 0.0 || options.lot_size < 0.0 {
+            return Err(ValuationError::Configuration(
+                "Cash, minimum trade value and lot size must be non-negative".to_string(),
+            ));
+        }
+        for symbol in targets.keys() {
+            if !holdings.iter().any(|h| h.symbol == *symbol && h.price > 0.0) {
+                return Err(ValuationError::MarketData(format!("No price for target {}", symbol)));
+            }
+        }
+        if let Some(h) = holdings.iter().find(|h| h.price <= 0.0 && h.quantity() > f64::EPSILON) {
+            return Err(ValuationError::MarketData(format!("No price for holding {}", h.symbol)));
+        }
+
+        let total_value = holdings.iter().map(|h| h.quantity() * h.price).sum::<f64>() + cash;
+        let investable = total_value * (1.0 - options.cash_buffer);
+        let buffer = total_value * options.cash_buffer;
+
+        let mut trades = Vec::new();
+        let mut purchases: Vec<(&RebalanceHolding, f64)> = Vec::new();
+        for holding in holdings {
+            let quantity = holding.quantity();
+            let target_weight = targets.get(&holding.symbol).copied().unwrap_or(0.0);
+            let difference = (target_weight * investable - quantity * holding.price) / holding.price.max(f64::MIN_POSITIVE);
+            let lot_size = self.lot_size(&holding.symbol);
+            if difference < 0.0 {
+                let sell = if target_weight == 0.0 { quantity } else { round_to_lot(-difference, lot_size).min(quantity) };
+                if sell > f64::EPSILON && sell * holding.price >= options.min_trade_value {
+                    trades.push(self.sale(holding, sell));
+                }
+            } else if difference > 0.0 {
+                purchases.push((holding, difference));
+            }
+        }
+
+        let sell_value: f64 = trades.iter().map(|t| t.value).sum();
+        let mut available = cash + sell_value - buffer;
+        purchases.sort_by(|a, b| (b.1 * b.0.price).total_cmp(&(a.1 * a.0.price)));
+        for (holding, wanted) in purchases {
+            let lot_size = self.lot_size(&holding.symbol);
+            let affordable = (available.max(0.0) / holding.price) * (1.0 - 1e-12);
+            let quantity = round_to_lot(wanted.min(affordable), lot_size);
+            let value = quantity * holding.price;
+            if quantity <= f64::EPSILON || value < options.min_trade_value {
+                continue;
+            }
+            available -= value;
+            trades.push(ProposedTrade {
+                side: TradeSide::Buy,
+                symbol: holding.symbol.clone(),
+                quantity,
+                price: holding.price,
+                value,
+                lots: Vec::new(),
+                realized_gain: 0.0,
+            });
+        }
+
+        let buy_value: f64 = trades.iter().filter(|t| t.side == TradeSide::Buy).map(|t| t.value).sum();
+        let cash_after = cash + sell_value - buy_value;
+        let after: Vec<RebalanceHolding> = holdings
+            .iter()
+            .map(|h| {
+                let traded: f64 = trades
+                    .iter()
+                    .filter(|t| t.symbol == h.symbol)
+                    .map(|t| if t.side == TradeSide::Buy { t.quantity } else { -t.quantity })
+                    .sum();
+                RebalanceHolding {
+                    symbol: h.symbol.clone(),
+                    price: h.price,
+                    lots: vec![TaxLot { id: String::new(), quantity: h.quantity() + traded, cost_basis: 0.0 }],
+                }
+            })
+            .collect();
+
+        Ok(RebalancePlan {
+            total_value,
+            cash_before: cash,
+            cash_after,
+            realized_gain: trades.iter().map(|t| t.realized_gain).sum(),
+            buy_value,
+            sell_value,
+            trades,
+            drift_after: self.drift(&after, targets, cash_after, 0.0)?,
+        })
+    }
+
+    fn lot_size(&self, symbol: &str) -> f64 {
+        self.options.lot_sizes.get(symbol).copied().unwrap_or(self.options.lot_size)
+    }
+
+    fn sale(&self, holding: &RebalanceHolding, quantity: f64) -> ProposedTrade {
+        let mut order: Vec<usize> = (0..holding.lots.len()).collect();
+        if self.options.lot_selection == LotSelection::MinimizeGains {
+            order.sort_by(|a, b| holding.lots[*b].cost_basis.total_cmp(&holding.lots[*a].cost_basis));
+        }
+        let mut remaining = quantity;
+        let mut lots = Vec::new();
+        for index in order {
+            if remaining <= f64::EPSILON {
+                break;
+            }
+            let lot = &holding.lots[index];
+            let relieved = lot.quantity.min(remaining);
+            if relieved <= 0.0 {
+                continue;
+            }
+            remaining -= relieved;
+            lots.push(LotRelief {
+                lot_index: index,
+                lot_id: lot.id.clone(),
+                quantity: relieved,
+                cost_basis: lot.cost_basis,
+                realized_gain: relieved * (holding.price - lot.cost_basis),
+            });
+        }
+        ProposedTrade {
+            side: TradeSide::Sell,
+            symbol: holding.symbol.clone(),
+            quantity,
+            price: holding.price,
+            value: quantity * holding.price,
+            realized_gain: lots.iter().map(|l| l.realized_gain).sum(),
+            lots,
+        }
+    }
+}
+
+impl Default for RebalanceEngine {
+    fn default() -> Self {
+        Self::new(RebalanceOptions::default())
+    }
+}
+
+fn validate_targets(targets: &HashMap<String, f64>) -> Result<()> {
+    if targets.values().any(|w| !w.is_finite() || *w < 0.0) {
+        return Err(ValuationError::Configuration("Target weights must be non-negative".to_string()));
+    }
+    if targets.values().sum::<f64>() > 1.0 + 1e-9 {
+        return Err(ValuationError::Configuration("Target weights sum to more than one".to_string()));
+    }
+    Ok(())
+}
+
+// Only long positions can be rebalanced: short lots have negative quantities
+fn validate_holdings(holdings: &[RebalanceHolding]) -> Result<()> {
+    let short: Vec<&str> = holdings
+        .iter()
+        .filter(|h| h.lots.iter().any(|l| l.quantity < 0.0))
+        .map(|h| h.symbol.as_str())
+        .collect();
+    if short.is_empty() {
+        Ok(())
+    } else {
+        Err(ValuationError::Portfolio(format!("Short positions cannot be rebalanced: {}", short.join(", "))))
+    }
+}
+
+// Largest multiple of `lot_size` not above `quantity` (the quantity itself when `lot_size` is 0)
+fn round_to_lot(quantity: f64, lot_size: f64) -> f64 {
+    if lot_size <= 0.0 {
+        return quantity;
+    }
+    // Tolerate representation error just below a whole number of lots
+    (quantity / lot_size + 1e-9).floor() * lot_size
+}
+
+// Scales model weights (e.g. percentages) to sum to `invested`, merging symbols case-insensitively
+pub fn normalize_weights(weights: &HashMap<String, f64>, invested: f64) -> Result<HashMap<String, f64>> {
+    let mut merged: BTreeMap<String, f64> = BTreeMap::new();
+    for (symbol, weight) in weights {
+        if !weight.is_finite() || *weight < 0.0 {
+            return Err(ValuationError::Configuration(format!("Invalid weight for {}", symbol)));
+        }
+        *merged.entry(symbol.trim().to_uppercase()).or_default() += weight;
+    }
+    let total: f64 = merged.values().sum();
+    if total <= 0.0 {
+        return Err(ValuationError::Configuration("Model portfolio has no weight".to_string()));
+    }
+    Ok(merged.into_iter().map(|(symbol, weight)| (symbol, weight / total * invested)).collect())
+}
@@ -1,8 +1,32 @@
-use crate::{Result, ValuationError, RiskMetrics};
+use crate::portfolio::value_positions;
+use crate::{
+    is_positive_semi_definite, nearest_correlation_matrix, CovarianceEstimator, Instrument, MarketContext,
+    MarketShift, Portfolio, PositionScenarioPnl, Result, ReturnSeries, RiskMetrics, Scenario, ScenarioValuation,
+    ShiftMode, ValuationError, Valuator,
+};
+use chrono::NaiveDate;
 use nalgebra as na;
 use rand::prelude::*;
 use rand_distr::StandardNormal;
-use statrs::distribution::{ContinuousCDF, Normal};
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{Continuous, ContinuousCDF, Normal};
+use std::collections::HashMap;
+
+// NaN P&L (e.g. from an unpriced instrument) has no place in a loss distribution
+fn check_finite(values: impl IntoIterator<Item = f64>) -> Result<()> {
+    if values.into_iter().all(f64::is_finite) {
+        Ok(())
+    } else {
+        Err(ValuationError::RiskCalculation("Non-finite P&L in the loss distribution".to_string()))
+    }
+}
+
+fn sorted_finite(values: impl IntoIterator<Item = f64>) -> Result<Vec<f64>> {
+    let mut sorted: Vec<f64> = values.into_iter().collect();
+    check_finite(sorted.iter().copied())?;
+    sorted.sort_by(f64::total_cmp);
+    Ok(sorted)
+}
 
 pub struct RiskEngine {
     confidence_level: f64,
@@ -24,8 +48,7 @@ impl RiskEngine {
             return Err(ValuationError::RiskCalculation("Empty returns vector".to_string()));
         }
 
-        let mut sorted_returns = returns.to_vec();
-        sorted_returns.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let sorted_returns = sorted_finite(returns.iter().copied())?;
         
         let index = ((1.0 - self.confidence_level) * returns.len() as f64) as usize;
         let var = -sorted_returns[index.min(sorted_returns.len() - 1)];
@@ -38,8 +61,7 @@ impl RiskEngine {
             return Err(ValuationError::RiskCalculation("Empty returns vector".to_string()));
         }
 
-        let mut sorted_returns = returns.to_vec();
-        sorted_returns.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let sorted_returns = sorted_finite(returns.iter().copied())?;
         
         let cutoff_index = ((1.0 - self.confidence_level) * returns.len() as f64) as usize;
         let tail_returns: Vec<f64> = sorted_returns.iter().take(cutoff_index + 1).cloned().collect();
@@ -65,6 +87,26 @@ impl RiskEngine {
         Ok(variance.sqrt())
     }
 
+    // Beta of `returns` against `benchmark_returns` (same dates): cov(r, b) / var(b)
+    pub fn calculate_beta(&self, returns: &[f64], benchmark_returns: &[f64]) -> Result<f64> {
+        if returns.len() != benchmark_returns.len() || returns.len() < 2 {
+            return Err(ValuationError::RiskCalculation("Insufficient or misaligned data for beta".to_string()));
+        }
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let benchmark_mean = benchmark_returns.iter().sum::<f64>() / n;
+        let covariance: f64 = returns
+            .iter()
+            .zip(benchmark_returns)
+            .map(|(r, b)| (r - mean) * (b - benchmark_mean))
+            .sum();
+        let benchmark_variance: f64 = benchmark_returns.iter().map(|b| (b - benchmark_mean).powi(2)).sum();
+        if benchmark_variance == 0.0 {
+            return Err(ValuationError::RiskCalculation("Benchmark returns have zero variance".to_string()));
+        }
+        Ok(covariance / benchmark_variance)
+    }
+
     pub fn simulate_portfolio_returns(
         &self,
         portfolio_value: f64,
@@ -122,58 +164,9 @@ impl RiskEngine {
         })
     }
 
+    // Correlation matrix of daily returns, one row of `returns_matrix` per asset
     pub fn calculate_correlation_matrix(&self, returns_matrix: &[Vec<f64>]) -> Result<na::DMatrix<f64>> {
-        if returns_matrix.is_empty() {
-            return Err(ValuationError::RiskCalculation("Empty returns matrix".to_string()));
-        }
-
-        let n_assets = returns_matrix.len();
-        let n_observations = returns_matrix[0].len();
-        
-        // Check all assets have same number of observations
-        for returns in returns_matrix {
-            if returns.len() != n_observations {
-                return Err(ValuationError::RiskCalculation("Inconsistent number of observations".to_string()));
-            }
-        }
-
-        let mut correlation_matrix = na::DMatrix::zeros(n_assets, n_assets);
-        
-        // Calculate means
-        let means: Vec<f64> = returns_matrix.iter()
-            .map(|returns| returns.iter().sum::<f64>() / returns.len() as f64)
-            .collect();
-
-        // Calculate correlation coefficients
-        for i in 0..n_assets {
-            for j in 0..n_assets {
-                if i == j {
-                    correlation_matrix[(i, j)] = 1.0;
-                } else {
-                    let numerator: f64 = (0..n_observations)
-                        .map(|k| (returns_matrix[i][k] - means[i]) * (returns_matrix[j][k] - means[j]))
-                        .sum();
-                    
-                    let var_i: f64 = returns_matrix[i].iter()
-                        .map(|&x| (x - means[i]).powi(2))
-                        .sum::<f64>();
-                    
-                    let var_j: f64 = returns_matrix[j].iter()
-                        .map(|&x| (x - means[j]).powi(2))
-                        .sum::<f64>();
-                    
-                    let denominator = (var_i * var_j).sqrt();
-                    
-                    if denominator > 0.0 {
-                        correlation_matrix[(i, j)] = numerator / denominator;
-                    } else {
-                        correlation_matrix[(i, j)] = 0.0;
-                    }
-                }
-            }
-        }
-        
-        Ok(correlation_matrix)
+        CovarianceEstimator::default().estimate_correlation(returns_matrix)
     }
 
     pub fn calculate_portfolio_var(
@@ -187,24 +180,28 @@ impl RiskEngine {
             return Err(ValuationError::RiskCalculation("Dimension mismatch in portfolio VaR calculation".to_string()));
         }
 
-        let n = weights.len();
-        let mut portfolio_variance = 0.0;
-        
-        for i in 0..n {
-            for j in 0..n {
-                portfolio_variance += weights[i] * weights[j] * volatilities[i] * volatilities[j] * correlation_matrix[(i, j)];
-            }
-        }
-        
-        let portfolio_volatility = portfolio_variance.sqrt();
+        // Inconsistent (non-PSD) correlations would give a negative variance
+        let repaired;
+        let correlation_matrix = if is_positive_semi_definite(correlation_matrix) {
+            correlation_matrix
+        } else {
+            repaired = nearest_correlation_matrix(correlation_matrix);
+            &repaired
+        };
+
+        let exposures = na::DVector::from_iterator(weights.len(), weights.iter().zip(volatilities).map(|(w, v)| w * v));
+        let portfolio_variance = exposures.dot(&(correlation_matrix * &exposures));
+
+        let portfolio_volatility = portfolio_variance.max(0.0).sqrt();
         let normal = Normal::new(0.0, 1.0).map_err(|e| ValuationError::RiskCalculation(e.to_string()))?;
-        let z_score = normal.inverse_cdf(1.0 - self.confidence_level);
+        let z_score = normal.inverse_cdf(self.confidence_level);
         
         let var = portfolio_value * portfolio_volatility * z_score * (self.time_horizon_days as f64 / 252.0).sqrt();
         
         Ok(var)
     }
 
+    // Euler allocation: VaR * x_i (ρx)_i / x'ρx with x = weights * volatilities
     pub fn calculate_component_var(
         &self,
         weights: &[f64],
@@ -213,50 +210,404 @@ impl RiskEngine {
         portfolio_value: f64,
     ) -> Result<Vec<f64>> {
         let portfolio_var = self.calculate_portfolio_var(weights, volatilities, correlation_matrix, portfolio_value)?;
-        let n = weights.len();
-        let mut component_vars = Vec::with_capacity(n);
-        
-        // Calculate marginal VaR for each asset
-        for i in 0..n {
-            let mut marginal_var = 0.0;
-            for j in 0..n {
-                marginal_var += weights[j] * volatilities[j] * correlation_matrix[(i, j)];
+        let repaired;
+        let correlation_matrix = if is_positive_semi_definite(correlation_matrix) {
+            correlation_matrix
+        } else {
+            repaired = nearest_correlation_matrix(correlation_matrix);
+            &repaired
+        };
+
+        let exposures = na::DVector::from_iterator(weights.len(), weights.iter().zip(volatilities).map(|(w, v)| w * v));
+        let marginal = correlation_matrix * &exposures;
+        let variance = exposures.dot(&marginal);
+        if variance <= 0.0 {
+            return Ok(vec![0.0; weights.len()]);
+        }
+        Ok(exposures
+            .iter()
+            .zip(marginal.iter())
+            .map(|(x, m)| portfolio_var * x * m / variance)
+            .collect())
+    }
+
+    // Marginal, component (Euler) and incremental VaR of each dollar exposure
+    pub fn decompose_var(&self, exposures: &[f64], covariance: &na::DMatrix<f64>) -> Result<VarDecomposition> {
+        if exposures.len() != covariance.nrows() || !covariance.is_square() {
+            return Err(ValuationError::RiskCalculation("Dimension mismatch in VaR decomposition".to_string()));
+        }
+        let normal = Normal::new(0.0, 1.0).map_err(|e| ValuationError::RiskCalculation(e.to_string()))?;
+        let z = normal.inverse_cdf(self.confidence_level);
+        let sigma = covariance * self.time_horizon_days.max(1) as f64;
+        let x = na::DVector::from_column_slice(exposures);
+        let sigma_x = &sigma * &x;
+        let std_dev = x.dot(&sigma_x).max(0.0).sqrt();
+        let var = z * std_dev;
+
+        let marginal: Vec<f64> = sigma_x
+            .iter()
+            .map(|v| if std_dev > 0.0 { z * v / std_dev } else { 0.0 })
+            .collect();
+        let component = exposures.iter().zip(&marginal).map(|(x, m)| x * m).collect();
+        let incremental = (0..exposures.len())
+            .map(|i| {
+                // Variance without exposure i: x'Σx - 2 x_i (Σx)_i + x_i² Σ_ii
+                let without = std_dev * std_dev - 2.0 * x[i] * sigma_x[i] + x[i] * x[i] * sigma[(i, i)];
+                var - z * without.max(0.0).sqrt()
+            })
+            .collect();
+
+        Ok(VarDecomposition {
+            var,
+            expected_shortfall: std_dev * normal.pdf(z) / (1.0 - self.confidence_level),
+            std_dev,
+            confidence_level: self.confidence_level,
+            horizon_days: self.time_horizon_days,
+            marginal,
+            component,
+            incremental,
+        })
+    }
+
+    // Each observation in `history` is replayed as a relative spot move with full revaluation, so options keep their convexity
+    pub fn historical_var(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        history: &ReturnSeries,
+        weighting: HistoricalWeighting,
+    ) -> Result<HistoricalVarResult> {
+        if history.is_empty() {
+            return Err(ValuationError::RiskCalculation("Empty return history".to_string()));
+        }
+        let n = history.len();
+
+        let returns: Vec<Vec<f64>> = match weighting {
+            HistoricalWeighting::VolatilityScaled { lambda } => {
+                check_decay_factor(lambda)?;
+                history.returns.iter().map(|series| volatility_scale(series, lambda)).collect()
+            }
+            _ => history.returns.clone(),
+        };
+        let weights: Vec<f64> = match weighting {
+            HistoricalWeighting::AgeWeighted { lambda } => {
+                check_decay_factor(lambda)?;
+                // Observation t is (n - 1 - t) days old
+                let norm = (1.0 - lambda) / (1.0 - lambda.powi(n as i32));
+                (0..n).map(|t| norm * lambda.powi((n - 1 - t) as i32)).collect()
+            }
+            _ => vec![1.0 / n as f64; n],
+        };
+
+        let context_for = |contexts: &HashMap<String, MarketContext>, instrument: &dyn Instrument| {
+            contexts.get(instrument.market_data_key()).cloned()
+        };
+        let (_, base_value) = value_positions(portfolio, instruments, valuator, |i| context_for(market_contexts, i))?;
+
+        let mut scenario_pnl = Vec::with_capacity(n);
+        for t in 0..n {
+            let scenario = Scenario {
+                name: history.dates[t].to_string(),
+                shifts: history.symbols.iter().zip(&returns)
+                    .map(|(symbol, series)| MarketShift::Spot {
+                        symbol: Some(symbol.clone()),
+                        mode: ShiftMode::Relative,
+                        amount: series[t],
+                    })
+                    .collect(),
+            };
+            let shocked = scenario.apply(market_contexts)?;
+            let (_, value) = value_positions(portfolio, instruments, valuator, |i| context_for(&shocked, i))?;
+            scenario_pnl.push(HistoricalScenarioPnl {
+                date: history.dates[t],
+                pnl: value - base_value,
+                weight: weights[t],
+            });
+        }
+
+        let (var, expected_shortfall) = self.weighted_tail_risk(&scenario_pnl)?;
+        let horizon_scale = (self.time_horizon_days.max(1) as f64).sqrt();
+
+        Ok(HistoricalVarResult {
+            var: var * horizon_scale,
+            expected_shortfall: expected_shortfall * horizon_scale,
+            confidence_level: self.confidence_level,
+            horizon_days: self.time_horizon_days,
+            observations: n,
+            base_value,
+            scenario_pnl,
+        })
+    }
+
+    // VaR and ES (as positive losses) of a weighted P&L distribution
+    fn weighted_tail_risk(&self, scenarios: &[HistoricalScenarioPnl]) -> Result<(f64, f64)> {
+        check_finite(scenarios.iter().map(|s| s.pnl))?;
+        let mut sorted: Vec<&HistoricalScenarioPnl> = scenarios.iter().collect();
+        sorted.sort_by(|a, b| a.pnl.total_cmp(&b.pnl));
+        let total_weight: f64 = sorted.iter().map(|s| s.weight).sum();
+        if sorted.is_empty() || total_weight <= 0.0 {
+            return Err(ValuationError::RiskCalculation("No weighted scenarios".to_string()));
+        }
+
+        let tail_probability = 1.0 - self.confidence_level;
+        let mut cumulative = 0.0;
+        let mut tail_loss = 0.0;
+        for scenario in &sorted {
+            let weight = scenario.weight / total_weight;
+            let used = weight.min(tail_probability - cumulative);
+            tail_loss += used * scenario.pnl;
+            cumulative += weight;
+            if cumulative >= tail_probability {
+                return Ok((-scenario.pnl, -tail_loss / tail_probability));
             }
-            marginal_var *= volatilities[i];
-            
-            let component_var = (weights[i] * portfolio_value * marginal_var / portfolio_var.abs()) * portfolio_var;
-            component_vars.push(component_var);
         }
-        
-        Ok(component_vars)
+        let worst = sorted[sorted.len() - 1].pnl;
+        Ok((-worst, -tail_loss / cumulative))
     }
 
-    pub fn stress_test(&self, base_value: f64, stress_scenarios: &[StressScenario]) -> Result<Vec<StressTestResult>> {
-        let mut results = Vec::new();
-        
-        for scenario in stress_scenarios {
-            let stressed_value = match scenario.scenario_type {
-                StressType::MarketShock => {
-                    base_value * (1.0 + scenario.shock_magnitude)
+    // Sample covariance matrix of daily returns, one row of `returns_matrix` per asset
+    pub fn calculate_covariance_matrix(&self, returns_matrix: &[Vec<f64>]) -> Result<na::DMatrix<f64>> {
+        CovarianceEstimator::default().estimate(returns_matrix)
+    }
+
+    // Dollar delta and gamma per underlying, in the order of `symbols`
+    pub fn underlying_exposures(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        symbols: &[String],
+    ) -> Result<Vec<UnderlyingExposure>> {
+        let mut exposures: Vec<UnderlyingExposure> = symbols
+            .iter()
+            .map(|symbol| UnderlyingExposure {
+                symbol: symbol.clone(),
+                spot: market_contexts.get(symbol).and_then(|c| c.spot_price).unwrap_or(0.0),
+                dollar_delta: 0.0,
+                dollar_gamma: 0.0,
+            })
+            .collect();
+
+        for position in &portfolio.positions {
+            let instrument = instruments.get(&position.instrument_id)
+                .ok_or_else(|| ValuationError::Portfolio(format!("Instrument not found: {}", position.instrument_id)))?;
+            let key = instrument.market_data_key();
+            let context = market_contexts.get(key)
+                .ok_or_else(|| ValuationError::MarketData(format!("No market data for {}", key)))?;
+            let greeks = valuator.calculate_greeks(instrument.as_ref(), context)?;
+            let index = symbols.iter().position(|s| s == key);
+
+            match (greeks.delta, index) {
+                (None, None) => continue,
+                (None, Some(_)) => {
+                    return Err(ValuationError::RiskCalculation(format!("No delta available for {}", instrument.id())));
                 }
-                StressType::VolatilityShock => {
-                    // Simplified volatility stress - in practice would be more complex
-                    let vol_impact = scenario.shock_magnitude * 0.1; // 10% of shock affects value
-                    base_value * (1.0 - vol_impact.abs())
+                (Some(_), None) => {
+                    return Err(ValuationError::RiskCalculation(format!("No return history for {}", key)));
                 }
-                StressType::RateShock => {
-                    // Simplified rate shock - duration would be needed for bonds
-                    let rate_impact = scenario.shock_magnitude * 0.05; // 5% of shock affects value
-                    base_value * (1.0 - rate_impact)
+                (Some(delta), Some(i)) => {
+                    let spot = exposures[i].spot;
+                    exposures[i].dollar_delta += delta * spot * position.quantity;
+                    exposures[i].dollar_gamma += greeks.gamma.unwrap_or(0.0) * spot * spot * position.quantity;
                 }
+            }
+        }
+        Ok(exposures)
+    }
+
+    // sqrt(d' Σ d) with d the dollar deltas and Σ the covariance scaled to the horizon
+    pub fn delta_normal_var(&self, exposures: &[UnderlyingExposure], covariance: &na::DMatrix<f64>) -> Result<ParametricVarResult> {
+        let (deltas, _, sigma) = self.parametric_inputs(exposures, covariance)?;
+        let variance = (deltas.transpose() * &sigma * &deltas)[(0, 0)];
+        let std_dev = variance.max(0.0).sqrt();
+
+        let normal = Normal::new(0.0, 1.0).map_err(|e| ValuationError::RiskCalculation(e.to_string()))?;
+        let z = normal.inverse_cdf(self.confidence_level);
+        Ok(ParametricVarResult {
+            method: ParametricMethod::DeltaNormal,
+            var: z * std_dev,
+            expected_shortfall: Some(std_dev * normal.pdf(z) / (1.0 - self.confidence_level)),
+            mean: 0.0,
+            std_dev,
+            skewness: 0.0,
+            excess_kurtosis: 0.0,
+            confidence_level: self.confidence_level,
+            horizon_days: self.time_horizon_days,
+        })
+    }
+
+    // Cornish-Fisher quantile from the first four moments of d'r + ½ r'Γr
+    pub fn delta_gamma_var(&self, exposures: &[UnderlyingExposure], covariance: &na::DMatrix<f64>) -> Result<ParametricVarResult> {
+        let (deltas, gammas, sigma) = self.parametric_inputs(exposures, covariance)?;
+        let gamma_matrix = na::DMatrix::from_diagonal(&gammas);
+        let a = &gamma_matrix * &sigma;
+        let a2 = &a * &a;
+        let sigma_d = &sigma * &deltas;
+
+        let mean = 0.5 * a.trace();
+        let variance = (deltas.transpose() * &sigma_d)[(0, 0)] + 0.5 * a2.trace();
+        if variance <= 0.0 {
+            return Ok(ParametricVarResult {
+                method: ParametricMethod::DeltaGamma,
+                var: -mean,
+                expected_shortfall: None,
+                mean,
+                std_dev: 0.0,
+                skewness: 0.0,
+                excess_kurtosis: 0.0,
+                confidence_level: self.confidence_level,
+                horizon_days: self.time_horizon_days,
+            });
+        }
+        let third = 3.0 * (sigma_d.transpose() * &gamma_matrix * &sigma_d)[(0, 0)] + (&a2 * &a).trace();
+        let fourth = 12.0 * (sigma_d.transpose() * &gamma_matrix * &sigma * &gamma_matrix * &sigma_d)[(0, 0)]
+            + 3.0 * (&a2 * &a2).trace();
+        let std_dev = variance.sqrt();
+        let skewness = third / variance.powf(1.5);
+        let excess_kurtosis = fourth / (variance * variance);
+
+        let normal = Normal::new(0.0, 1.0).map_err(|e| ValuationError::RiskCalculation(e.to_string()))?;
+        let z = normal.inverse_cdf(1.0 - self.confidence_level);
+        let z_cf = z
+            + (z * z - 1.0) * skewness / 6.0
+            + (z.powi(3) - 3.0 * z) * excess_kurtosis / 24.0
+            - (2.0 * z.powi(3) - 5.0 * z) * skewness * skewness / 36.0;
+
+        Ok(ParametricVarResult {
+            method: ParametricMethod::DeltaGamma,
+            var: -(mean + std_dev * z_cf),
+            expected_shortfall: None,
+            mean,
+            std_dev,
+            skewness,
+            excess_kurtosis,
+            confidence_level: self.confidence_level,
+            horizon_days: self.time_horizon_days,
+        })
+    }
+
+    fn parametric_inputs(
+        &self,
+        exposures: &[UnderlyingExposure],
+        covariance: &na::DMatrix<f64>,
+    ) -> Result<(na::DVector<f64>, na::DVector<f64>, na::DMatrix<f64>)> {
+        if exposures.len() != covariance.nrows() || !covariance.is_square() {
+            return Err(ValuationError::RiskCalculation("Dimension mismatch in parametric VaR calculation".to_string()));
+        }
+        let deltas = na::DVector::from_iterator(exposures.len(), exposures.iter().map(|e| e.dollar_delta));
+        let gammas = na::DVector::from_iterator(exposures.len(), exposures.iter().map(|e| e.dollar_gamma));
+        let horizon_covariance = covariance * self.time_horizon_days.max(1) as f64;
+        Ok((deltas, gammas, horizon_covariance))
+    }
+
+    // L L' by Cholesky, or by eigen decomposition with negative eigenvalues clipped when not positive definite
+    pub fn factorize_covariance(&self, covariance: &na::DMatrix<f64>) -> Result<na::DMatrix<f64>> {
+        if !covariance.is_square() {
+            return Err(ValuationError::RiskCalculation("Covariance matrix must be square".to_string()));
+        }
+        if let Some(cholesky) = covariance.clone().cholesky() {
+            return Ok(cholesky.l());
+        }
+        let eigen = na::SymmetricEigen::new(covariance.clone());
+        let sqrt_values = eigen.eigenvalues.map(|v| v.max(0.0).sqrt());
+        Ok(&eigen.eigenvectors * na::DMatrix::from_diagonal(&sqrt_values))
+    }
+
+    // Correlated log-returns over the horizon shock the spots and every position is revalued on each path
+    pub fn monte_carlo_var(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        symbols: &[String],
+        covariance: &na::DMatrix<f64>,
+    ) -> Result<MonteCarloVarResult> {
+        if symbols.len() != covariance.nrows() {
+            return Err(ValuationError::RiskCalculation("Dimension mismatch in Monte Carlo VaR calculation".to_string()));
+        }
+        if self.num_simulations == 0 {
+            return Err(ValuationError::RiskCalculation("Number of simulations must be positive".to_string()));
+        }
+        let horizon = self.time_horizon_days.max(1) as f64;
+        let factor = self.factorize_covariance(&(covariance * horizon))?;
+        // Martingale correction so each simulated spot has the current spot as its mean
+        let drift_correction: Vec<f64> = (0..symbols.len()).map(|i| -0.5 * covariance[(i, i)] * horizon).collect();
+
+        let context_for = |contexts: &HashMap<String, MarketContext>, instrument: &dyn Instrument| {
+            contexts.get(instrument.market_data_key()).cloned()
+        };
+        let (_, base_value) = value_positions(portfolio, instruments, valuator, |i| context_for(market_contexts, i))?;
+
+        let mut rng = thread_rng();
+        let mut pnl = Vec::with_capacity(self.num_simulations);
+        for _ in 0..self.num_simulations {
+            let z = na::DVector::from_fn(symbols.len(), |_, _| rng.sample::<f64, _>(StandardNormal));
+            let shocks = &factor * z;
+            let scenario = Scenario {
+                name: String::new(),
+                shifts: symbols.iter().enumerate()
+                    .map(|(i, symbol)| MarketShift::Spot {
+                        symbol: Some(symbol.clone()),
+                        mode: ShiftMode::Relative,
+                        amount: (shocks[i] + drift_correction[i]).exp() - 1.0,
+                    })
+                    .collect(),
             };
-            
+            let shocked = scenario.apply(market_contexts)?;
+            let (_, value) = value_positions(portfolio, instruments, valuator, |i| context_for(&shocked, i))?;
+            pnl.push(value - base_value);
+        }
+        let pnl = sorted_finite(pnl)?;
+
+        Ok(MonteCarloVarResult {
+            var: self.calculate_var(&pnl)?,
+            expected_shortfall: self.calculate_expected_shortfall(&pnl)?,
+            mean_pnl: pnl.iter().sum::<f64>() / pnl.len() as f64,
+            confidence_level: self.confidence_level,
+            horizon_days: self.time_horizon_days,
+            simulations: self.num_simulations,
+            base_value,
+            pnl_distribution: pnl,
+        })
+    }
+
+    // Full revaluation under each scenario's shocked market data
+    pub fn stress_test(
+        &self,
+        portfolio: &Portfolio,
+        instruments: &HashMap<String, Box<dyn Instrument + Send + Sync>>,
+        valuator: &dyn Valuator,
+        market_contexts: &HashMap<String, MarketContext>,
+        stress_scenarios: &[StressScenario],
+    ) -> Result<Vec<StressTestResult>> {
+        let context_for = |contexts: &HashMap<String, MarketContext>, instrument: &dyn Instrument| {
+            contexts.get(instrument.market_data_key()).cloned()
+        };
+        let (base_positions, base_value) = value_positions(portfolio, instruments, valuator, |i| context_for(market_contexts, i))?;
+
+        let mut results = Vec::new();
+        for stress in stress_scenarios {
+            let shocked = stress.to_scenario().apply(market_contexts)?;
+            let (positions, stressed_value) = value_positions(portfolio, instruments, valuator, |i| context_for(&shocked, i))?;
+            let revaluation = ScenarioValuation::from_revaluation(
+                &stress.name,
+                &base_positions,
+                base_value,
+                &positions,
+                stressed_value,
+            );
+
             results.push(StressTestResult {
-                scenario_name: scenario.name.clone(),
+                scenario_name: stress.name.clone(),
                 base_value,
                 stressed_value,
-                pnl: stressed_value - base_value,
-                pnl_percentage: (stressed_value - base_value) / base_value * 100.0,
+                pnl: revaluation.pnl,
+                pnl_percentage: revaluation.pnl_percentage,
+                positions: revaluation.positions,
             });
         }
         
@@ -264,27 +615,158 @@ impl RiskEngine {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum HistoricalWeighting {
+    Equal,
+    // Boudoukh-Richardson-Whitelaw: an observation k days old has weight proportional to lambda^k
+    AgeWeighted { lambda: f64 },
+    // Hull-White: each return is rescaled by today's EWMA volatility over the volatility at the time
+    VolatilityScaled { lambda: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalScenarioPnl {
+    pub date: NaiveDate,
+    pub pnl: f64,
+    pub weight: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalVarResult {
+    pub var: f64,
+    pub expected_shortfall: f64,
+    pub confidence_level: f64,
+    pub horizon_days: i64,
+    pub observations: usize,
+    pub base_value: f64,
+    pub scenario_pnl: Vec<HistoricalScenarioPnl>,
+}
+
+fn check_decay_factor(lambda: f64) -> Result<()> {
+    if lambda > 0.0 && lambda < 1.0 {
+        Ok(())
+    } else {
+        Err(ValuationError::RiskCalculation(format!("Decay factor must be in (0, 1), got {}", lambda)))
+    }
+}
+
+// Latest EWMA volatility over the one prevailing when each return was observed
+fn volatility_scale(returns: &[f64], lambda: f64) -> Vec<f64> {
+    let mut variance = returns.iter().map(|r| r * r).sum::<f64>() / returns.len().max(1) as f64;
+    let mut vol_then = Vec::with_capacity(returns.len());
+    for r in returns {
+        vol_then.push(variance.sqrt());
+        variance = lambda * variance + (1.0 - lambda) * r * r;
+    }
+    let vol_now = variance.sqrt();
+    returns
+        .iter()
+        .zip(vol_then)
+        .map(|(r, vol)| if vol > 0.0 { r * vol_now / vol } else { *r })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VarDecomposition {
+    pub var: f64,
+    pub expected_shortfall: f64,
+    pub std_dev: f64,
+    pub confidence_level: f64,
+    pub horizon_days: i64,
+    pub marginal: Vec<f64>,
+    pub component: Vec<f64>,
+    pub incremental: Vec<f64>,
+}
+
+// First- and second-order spot exposure of the portfolio to one underlying
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnderlyingExposure {
+    pub symbol: String,
+    pub spot: f64,
+    // Value change per unit relative move of the underlying (sum of delta * spot * quantity)
+    pub dollar_delta: f64,
+    // Sum of gamma * spot^2 * quantity
+    pub dollar_gamma: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParametricMethod {
+    DeltaNormal,
+    DeltaGamma,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParametricVarResult {
+    pub method: ParametricMethod,
+    pub var: f64,
+    pub expected_shortfall: Option<f64>,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub skewness: f64,
+    pub excess_kurtosis: f64,
+    pub confidence_level: f64,
+    pub horizon_days: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonteCarloVarResult {
+    pub var: f64,
+    pub expected_shortfall: f64,
+    pub mean_pnl: f64,
+    pub confidence_level: f64,
+    pub horizon_days: i64,
+    pub simulations: usize,
+    pub base_value: f64,
+    // Simulated P&L per path, sorted from worst to best
+    pub pnl_distribution: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StressScenario {
     pub name: String,
     pub scenario_type: StressType,
-    pub shock_magnitude: f64, // As a percentage (e.g., -0.20 for -20%)
+    pub shock_magnitude: f64, // As a fraction (e.g., -0.20 for -20%); rate shocks are absolute (0.01 = +100bp)
+}
+
+impl StressScenario {
+    pub fn to_scenario(&self) -> Scenario {
+        let shift = match self.scenario_type {
+            StressType::MarketShock => MarketShift::Spot {
+                symbol: None,
+                mode: ShiftMode::Relative,
+                amount: self.shock_magnitude,
+            },
+            StressType::VolatilityShock => MarketShift::Volatility {
+                symbol: None,
+                mode: ShiftMode::Relative,
+                amount: self.shock_magnitude,
+            },
+            StressType::RateShock => MarketShift::Rate {
+                tenor: None,
+                amount: self.shock_magnitude,
+            },
+        };
+        Scenario { name: self.name.clone(), shifts: vec![shift] }
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum StressType {
     MarketShock,
     VolatilityShock,
     RateShock,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StressTestResult {
     pub scenario_name: String,
     pub base_value: f64,
     pub stressed_value: f64,
     pub pnl: f64,
     pub pnl_percentage: f64,
+    pub positions: Vec<PositionScenarioPnl>,
 }
 
 impl Default for RiskEngine {
@@ -0,0 +1,161 @@
+use crate::{MarketContext, PositionValuation, Result, ValuationError};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShiftMode {
+    // Added to the level (e.g. +0.05 volatility = 5 vol points)
+    Absolute,
+    // Scales the level (e.g. -0.10 = down 10%)
+    Relative,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MarketShift {
+    Spot {
+        symbol: Option<String>,
+        mode: ShiftMode,
+        amount: f64,
+    },
+    Volatility {
+        symbol: Option<String>,
+        mode: ShiftMode,
+        amount: f64,
+    },
+    // Absolute rate shift in decimal (0.01 = 100bp)
+    Rate {
+        tenor: Option<String>,
+        amount: f64,
+    },
+    // Moves the valuation date forward, shortening time to expiry/maturity
+    TimeRoll {
+        days: i64,
+    },
+}
+
+impl MarketShift {
+    fn applies_to(symbol: &Option<String>, key: &str) -> bool {
+        symbol.as_deref().is_none_or(|s| s.eq_ignore_ascii_case(key))
+    }
+
+    fn shift_level(level: f64, mode: ShiftMode, amount: f64) -> f64 {
+        match mode {
+            ShiftMode::Absolute => level + amount,
+            ShiftMode::Relative => level * (1.0 + amount),
+        }
+    }
+
+    pub fn apply(&self, key: &str, context: &mut MarketContext) -> Result<()> {
+        match self {
+            MarketShift::Spot { symbol, mode, amount } => {
+                if Self::applies_to(symbol, key) {
+                    context.spot_price = context.spot_price
+                        .map(|s| Self::shift_level(s, *mode, *amount).max(0.0));
+                }
+            }
+            MarketShift::Volatility { symbol, mode, amount } => {
+                if Self::applies_to(symbol, key) {
+                    context.volatility = context.volatility
+                        .map(|v| Self::shift_level(v, *mode, *amount).max(0.0));
+                }
+            }
+            MarketShift::Rate { tenor: None, amount } => {
+                context.risk_free_rate += amount;
+                if let Some(curve) = context.yield_curve.as_mut() {
+                    curve.values_mut().for_each(|r| *r += amount);
+                }
+            }
+            MarketShift::Rate { tenor: Some(tenor), amount } => {
+                let curve = context.yield_curve.as_mut()
+                    .ok_or_else(|| ValuationError::MarketData(format!("No yield curve for {} to shift at the {} tenor", key, tenor)))?;
+                let rate = curve.iter_mut()
+                    .find(|(t, _)| t.eq_ignore_ascii_case(tenor))
+                    .map(|(_, r)| r)
+                    .ok_or_else(|| ValuationError::MarketData(format!("Yield curve of {} has no {} tenor", key, tenor)))?;
+                *rate += amount;
+            }
+            MarketShift::TimeRoll { days } => {
+                context.timestamp += Duration::days(*days);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    pub shifts: Vec<MarketShift>,
+}
+
+impl Scenario {
+    // Applies the shifts in order to a copy of the contexts
+    pub fn apply(&self, contexts: &HashMap<String, MarketContext>) -> Result<HashMap<String, MarketContext>> {
+        contexts
+            .iter()
+            .map(|(key, context)| {
+                let mut shifted = context.clone();
+                for shift in &self.shifts {
+                    shift.apply(key, &mut shifted)?;
+                }
+                Ok((key.clone(), shifted))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionScenarioPnl {
+    pub position_id: String,
+    pub instrument_id: String,
+    pub base_value: f64,
+    pub scenario_value: f64,
+    pub pnl: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioValuation {
+    pub scenario_name: String,
+    pub base_value: f64,
+    pub scenario_value: f64,
+    pub pnl: f64,
+    pub pnl_percentage: f64,
+    pub positions: Vec<PositionScenarioPnl>,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl ScenarioValuation {
+    pub fn from_revaluation(
+        scenario_name: &str,
+        base_positions: &[PositionValuation],
+        base_value: f64,
+        scenario_positions: &[PositionValuation],
+        scenario_value: f64,
+    ) -> Self {
+        let positions = base_positions
+            .iter()
+            .zip(scenario_positions)
+            .map(|(base, shocked)| PositionScenarioPnl {
+                position_id: base.position_id.clone(),
+                instrument_id: base.instrument_id.clone(),
+                base_value: base.total_value,
+                scenario_value: shocked.total_value,
+                pnl: shocked.total_value - base.total_value,
+            })
+            .collect();
+
+        let pnl = scenario_value - base_value;
+        Self {
+            scenario_name: scenario_name.to_string(),
+            base_value,
+            scenario_value,
+            pnl,
+            pnl_percentage: if base_value != 0.0 { pnl / base_value * 100.0 } else { 0.0 },
+            positions,
+            timestamp: Utc::now(),
+        }
+    }
+}
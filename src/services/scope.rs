@@ -0,0 +1,100 @@
+use crate::{Account, CorporateAction, LedgerEditError, LedgerEntry, ReliefMethod};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+// Portfolio the unscoped routes act on
+pub const DEFAULT_PORTFOLIO_ID: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum PortfolioScopeError {
+    #[error("portfolio not found")]
+    NotFound(String),
+    #[error("id must be 1-64 letters, digits, '-' or '_'")]
+    InvalidId(String),
+    #[error("the default portfolio cannot be deleted")]
+    DefaultNotDeletable,
+}
+
+impl PortfolioScopeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PortfolioScopeError::NotFound(_) => "portfolio_not_found",
+            PortfolioScopeError::InvalidId(_) => "invalid_portfolio_id",
+            PortfolioScopeError::DefaultNotDeletable => "default_portfolio",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortfolioScope {
+    // Unscoped routes (`/portfolio/...`, `/transactions`, `/stream`)
+    Default,
+    // Routes under `/portfolios/:portfolio_id`
+    Portfolio(String),
+}
+
+impl PortfolioScope {
+    pub fn from_path(portfolio_id: Option<&str>) -> Self {
+        match portfolio_id {
+            Some(id) => PortfolioScope::Portfolio(id.to_string()),
+            None => PortfolioScope::Default,
+        }
+    }
+
+    // Portfolio ID that has to be looked up before the request can use it (none for the default portfolio)
+    pub fn lookup(&self) -> Option<&str> {
+        match self {
+            PortfolioScope::Default => None,
+            PortfolioScope::Portfolio(id) => Some(id),
+        }
+    }
+
+    pub fn resolve(self, stored: bool) -> std::result::Result<String, PortfolioScopeError> {
+        match self {
+            PortfolioScope::Default => Ok(DEFAULT_PORTFOLIO_ID.to_string()),
+            PortfolioScope::Portfolio(id) if stored => Ok(id),
+            PortfolioScope::Portfolio(id) => Err(PortfolioScopeError::NotFound(id)),
+        }
+    }
+}
+
+// Checks the ID of a new portfolio: 1-64 letters, digits, '-' or '_'
+pub fn validate_portfolio_id(id: &str) -> std::result::Result<(), PortfolioScopeError> {
+    let valid = !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(PortfolioScopeError::InvalidId(id.to_string()))
+    }
+}
+
+pub fn check_deletable(portfolio_id: &str) -> std::result::Result<(), PortfolioScopeError> {
+    if portfolio_id == DEFAULT_PORTFOLIO_ID {
+        Err(PortfolioScopeError::DefaultNotDeletable)
+    } else {
+        Ok(())
+    }
+}
+
+// Settings that decide how a portfolio's own ledger is replayed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioSettings {
+    pub lot_relief: ReliefMethod,
+    pub allow_short: bool,
+}
+
+impl PortfolioSettings {
+    pub fn account(&self) -> Account {
+        Account::new(self.lot_relief).with_short_sales(self.allow_short)
+    }
+
+    // Fails on the first entry the settings do not allow, e.g. a short sale once shorting is off
+    pub fn revalidate(
+        &self,
+        entries: &[LedgerEntry],
+        actions: &[CorporateAction],
+        through: NaiveDate,
+    ) -> std::result::Result<Account, LedgerEditError> {
+        self.account().replay_validated(entries, actions, through)
+    }
+}
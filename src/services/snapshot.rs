@@ -0,0 +1,173 @@
+use crate::{DailyCloses, LedgerTrade, Result, ValuationError};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionSnapshot {
+    pub symbol: String,
+    pub quantity: f64,
+    pub price: f64,
+    pub value: f64,
+    pub cost_basis: f64,
+    pub unrealized_pnl: f64,
+}
+
+// Value at the end of `as_of` and that day's external flow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NavSnapshot {
+    pub portfolio_id: String,
+    pub as_of: NaiveDate,
+    pub total_value: f64,
+    pub cost_basis: f64,
+    pub unrealized_pnl: f64,
+    pub net_flow: f64,
+    pub positions: Vec<PositionSnapshot>,
+}
+
+// Replays the ledger and snapshots the book at the end of each of `dates` (ascending)
+pub fn snapshots_from_ledger(
+    portfolio_id: &str,
+    trades: &[LedgerTrade],
+    closes: &DailyCloses,
+    dates: &[NaiveDate],
+) -> Vec<NavSnapshot> {
+    let mut sorted: Vec<&LedgerTrade> = trades.iter().collect();
+    sorted.sort_by_key(|t| t.date);
+    let mut pending = sorted.into_iter().peekable();
+
+    let mut lots: BTreeMap<&str, VecDeque<(f64, f64)>> = BTreeMap::new();
+    let mut last_trade_price: HashMap<&str, f64> = HashMap::new();
+    let mut snapshots = Vec::with_capacity(dates.len());
+    let mut previous: Option<NaiveDate> = None;
+
+    for &as_of in dates {
+        // Trades since the previous snapshot are this snapshot's flows; trades before the first
+        // date only build the opening book
+        let mut net_flow = 0.0;
+        while let Some(trade) = pending.next_if(|t| t.date <= as_of) {
+            let symbol_lots = lots.entry(trade.symbol.as_str()).or_default();
+            if trade.price > 0.0 {
+                last_trade_price.insert(trade.symbol.as_str(), trade.price);
+            }
+            if previous.is_some() || trade.date == as_of {
+                net_flow += trade.quantity * trade.price;
+            }
+            // Relieve lots on the other side first, then open a lot with the rest
+            let mut remaining = trade.quantity;
+            while remaining.abs() > f64::EPSILON {
+                let Some(front) = symbol_lots.front_mut().filter(|lot| lot.0 * remaining < 0.0) else { break };
+                let relieved = front.0.abs().min(remaining.abs()) * remaining.signum();
+                front.0 += relieved;
+                remaining -= relieved;
+                if front.0.abs() <= f64::EPSILON {
+                    symbol_lots.pop_front();
+                }
+            }
+            if remaining.abs() > f64::EPSILON {
+                symbol_lots.push_back((remaining, trade.price));
+            }
+        }
+
+        let positions: Vec<PositionSnapshot> = lots
+            .iter()
+            .filter_map(|(symbol, symbol_lots)| {
+                let quantity: f64 = symbol_lots.iter().map(|(q, _)| q).sum();
+                if quantity.abs() <= f64::EPSILON {
+                    return None;
+                }
+                let cost_basis: f64 = symbol_lots.iter().map(|(q, p)| q * p).sum();
+                let price = closes
+                    .get(*symbol)
+                    .and_then(|s| s.range(..=as_of).next_back().map(|(_, p)| *p))
+                    .or_else(|| last_trade_price.get(symbol).copied())
+                    .unwrap_or(0.0);
+                let value = quantity * price;
+                Some(PositionSnapshot {
+                    symbol: symbol.to_string(),
+                    quantity,
+                    price,
+                    value,
+                    cost_basis,
+                    unrealized_pnl: value - cost_basis,
+                })
+            })
+            .collect();
+
+        let total_value: f64 = positions.iter().map(|p| p.value).sum();
+        let cost_basis: f64 = positions.iter().map(|p| p.cost_basis).sum();
+        snapshots.push(NavSnapshot {
+            portfolio_id: portfolio_id.to_string(),
+            as_of,
+            total_value,
+            cost_basis,
+            unrealized_pnl: total_value - cost_basis,
+            net_flow,
+            positions,
+        });
+        previous = Some(as_of);
+    }
+    snapshots
+}
+
+// End-of-day cut-off in a market timezone
+#[derive(Debug, Clone, Copy)]
+pub struct EodSchedule {
+    pub cutoff: NaiveTime,
+    pub timezone: Tz,
+}
+
+impl EodSchedule {
+    pub fn new(cutoff: NaiveTime, timezone: Tz) -> Self {
+        Self { cutoff, timezone }
+    }
+
+    // Parses a cut-off like "16:00" and an IANA timezone like "America/New_York"
+    pub fn parse(cutoff: &str, timezone: &str) -> Result<Self> {
+        let cutoff = NaiveTime::parse_from_str(cutoff, "%H:%M")
+            .map_err(|e| ValuationError::Configuration(format!("Invalid cut-off time {}: {}", cutoff, e)))?;
+        let timezone: Tz = timezone
+            .parse()
+            .map_err(|e| ValuationError::Configuration(format!("Invalid timezone {}: {}", timezone, e)))?;
+        Ok(Self::new(cutoff, timezone))
+    }
+
+    fn is_business_day(date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+    }
+
+    // Cut-off instant of a local business date
+    pub fn cutoff_at(&self, date: NaiveDate) -> DateTime<Utc> {
+        let local = date.and_time(self.cutoff);
+        // Around DST changes take the earliest valid instant (or shift an hour past a gap)
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| self.timezone.from_local_datetime(&(local + Duration::hours(1))).earliest())
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+    }
+
+    // Most recent business date whose cut-off is at or before `now`
+    pub fn last_completed(&self, now: DateTime<Utc>) -> NaiveDate {
+        let mut date = now.with_timezone(&self.timezone).date_naive();
+        while !Self::is_business_day(date) || self.cutoff_at(date) > now {
+            date -= Duration::days(1);
+        }
+        date
+    }
+
+    // Next cut-off strictly after `now`
+    pub fn next_run(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let mut date = now.with_timezone(&self.timezone).date_naive();
+        while !Self::is_business_day(date) || self.cutoff_at(date) <= now {
+            date += Duration::days(1);
+        }
+        self.cutoff_at(date)
+    }
+
+    pub fn business_days(from: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        from.iter_days().take_while(|d| *d <= to).filter(|d| Self::is_business_day(*d)).collect()
+    }
+}
@@ -0,0 +1,170 @@
+use chrono::{NaiveDate, Utc};
+use std::collections::{BTreeMap, HashMap};
+use valuation_service::{
+    brinson_fachler, daily_attribution_periods, link_attribution, make_valuator, AttributionGrouping, Benchmark,
+    DailyCloses, Instrument, InstrumentClassification, LedgerTrade, MarketContext, Portfolio,
+    PortfolioValuationService, PricingModelKind, SegmentPerformance, Stock,
+};
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+fn segment(name: &str, weight: f64, return_rate: f64) -> SegmentPerformance {
+    SegmentPerformance { segment: name.to_string(), weight, return_rate }
+}
+
+fn context(spot: f64) -> MarketContext {
+    MarketContext {
+        risk_free_rate: 0.04,
+        dividend_yield: None,
+        volatility: Some(0.2),
+        spot_price: Some(spot),
+        forward_curve: None,
+        yield_curve: None,
+        timestamp: Utc::now(),
+    }
+}
+
+#[test]
+fn test_brinson_fachler_effects() {
+    let portfolio = [segment("Tech", 0.6, 0.05), segment("Energy", 0.4, 0.01)];
+    let benchmark = [segment("Tech", 0.5, 0.04), segment("Energy", 0.3, 0.02), segment("Utilities", 0.2, 0.00)];
+    let result = brinson_fachler(date(2024, 1, 1), date(2024, 1, 31), &portfolio, &benchmark).unwrap();
+
+    // R_p = 3.4%, R_b = 2.6%
+    assert!((result.portfolio_return - 0.034).abs() < 1e-12);
+    assert!((result.benchmark_return - 0.026).abs() < 1e-12);
+    let tech = result.segments.iter().find(|s| s.segment == "Tech").unwrap();
+    assert!((tech.allocation - 0.1 * (0.04 - 0.026)).abs() < 1e-12);
+    assert!((tech.selection - 0.5 * 0.01).abs() < 1e-12);
+    assert!((tech.interaction - 0.1 * 0.01).abs() < 1e-12);
+    // Benchmark-only segment: underweighting a laggard is pure allocation
+    let utilities = result.segments.iter().find(|s| s.segment == "Utilities").unwrap();
+    assert!((utilities.allocation - (-0.2) * (0.0 - 0.026)).abs() < 1e-12);
+    assert_eq!(utilities.selection, 0.0);
+    assert_eq!(utilities.interaction, 0.0);
+
+    let effects = result.allocation + result.selection + result.interaction;
+    assert!((effects - result.excess_return).abs() < 1e-12);
+    assert!(brinson_fachler(date(2024, 1, 1), date(2024, 1, 31), &portfolio, &[]).is_err());
+}
+
+#[test]
+fn test_carino_linking_adds_up_to_compounded_excess() {
+    let months = [
+        ([segment("Tech", 0.6, 0.05), segment("Energy", 0.4, 0.01)], [segment("Tech", 0.5, 0.04), segment("Energy", 0.5, 0.02)]),
+        ([segment("Tech", 0.5, -0.03), segment("Energy", 0.5, 0.04)], [segment("Tech", 0.5, -0.02), segment("Energy", 0.5, 0.01)]),
+        ([segment("Tech", 0.7, 0.02), segment("Energy", 0.3, 0.02)], [segment("Tech", 0.5, 0.02), segment("Energy", 0.5, 0.02)]),
+    ];
+    let periods = months
+        .iter()
+        .enumerate()
+        .map(|(i, (p, b))| brinson_fachler(date(2024, i as u32 + 1, 1), date(2024, i as u32 + 2, 1), p, b).unwrap())
+        .collect();
+    let linked = link_attribution(periods).unwrap();
+
+    let compound = |rs: [f64; 3]| rs.iter().fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0;
+    let rp = compound([0.034, 0.005, 0.02]);
+    let rb = compound([0.03, -0.005, 0.02]);
+    assert!((linked.portfolio_return - rp).abs() < 1e-12);
+    assert!((linked.benchmark_return - rb).abs() < 1e-12);
+    // Arithmetic sums of the monthly effects would not match the quarter's excess; linked ones do
+    let effects = linked.allocation + linked.selection + linked.interaction;
+    assert!((effects - (rp - rb)).abs() < 1e-12);
+    let segment_total: f64 = linked.segments.iter().map(|s| s.total).sum();
+    assert!((segment_total - (rp - rb)).abs() < 1e-12);
+    assert_eq!(linked.start_date, date(2024, 1, 1));
+    assert_eq!(linked.end_date, date(2024, 4, 1));
+    assert!(link_attribution(Vec::new()).is_err());
+}
+
+#[test]
+fn test_daily_attribution_from_ledger() {
+    let closes: DailyCloses = HashMap::from([
+        ("AAPL".to_string(), BTreeMap::from([(date(2024, 3, 1), 100.0), (date(2024, 3, 4), 104.0), (date(2024, 3, 5), 102.0), (date(2024, 3, 6), 105.0)])),
+        ("XOM".to_string(), BTreeMap::from([(date(2024, 3, 1), 50.0), (date(2024, 3, 4), 49.0), (date(2024, 3, 5), 51.0), (date(2024, 3, 6), 51.0)])),
+        ("XLK".to_string(), BTreeMap::from([(date(2024, 3, 1), 200.0), (date(2024, 3, 4), 204.0), (date(2024, 3, 5), 202.0), (date(2024, 3, 6), 206.0)])),
+        ("XLE".to_string(), BTreeMap::from([(date(2024, 3, 1), 80.0), (date(2024, 3, 4), 79.0), (date(2024, 3, 5), 80.0), (date(2024, 3, 6), 81.0)])),
+    ]);
+    let trades = vec![
+        LedgerTrade { date: date(2024, 3, 1), symbol: "AAPL".to_string(), quantity: 10.0, price: 100.0 },
+        // Bought after the first period starts: only held from the 3/4 close
+        LedgerTrade { date: date(2024, 3, 4), symbol: "XOM".to_string(), quantity: 20.0, price: 49.0 },
+    ];
+    let segments = HashMap::from([
+        ("AAPL".to_string(), "Tech".to_string()),
+        ("XLK".to_string(), "Tech".to_string()),
+        ("XOM".to_string(), "Energy".to_string()),
+        ("XLE".to_string(), "Energy".to_string()),
+    ]);
+    let benchmark = Benchmark::parse("XLK:0.5,XLE:0.5").unwrap();
+
+    let periods = daily_attribution_periods(&trades, &closes, &segments, &benchmark, None, date(2024, 3, 6)).unwrap();
+    assert_eq!(periods.len(), 3);
+    assert!((periods[0].portfolio_return - 0.04).abs() < 1e-12);
+    let energy = periods[1].segments.iter().find(|s| s.segment == "Energy").unwrap();
+    assert!((energy.portfolio_weight - 980.0 / 2_020.0).abs() < 1e-12);
+
+    let linked = link_attribution(periods).unwrap();
+    let effects = linked.allocation + linked.selection + linked.interaction;
+    assert!((effects - linked.excess_return).abs() < 1e-12);
+
+    // The range starts after the close of `from`
+    let ranged = daily_attribution_periods(&trades, &closes, &segments, &benchmark, Some(date(2024, 3, 5)), date(2024, 3, 6)).unwrap();
+    assert_eq!(ranged.len(), 1);
+    assert_eq!(ranged[0].start_date, date(2024, 3, 5));
+}
+
+#[tokio::test]
+async fn test_portfolio_attribution_between_valuations() {
+    let mut portfolio = Portfolio::new("attribution".to_string(), "USD".to_string());
+    let mut instruments: HashMap<String, Box<dyn Instrument + Send + Sync>> = HashMap::new();
+    for (symbol, sector, shares) in [("AAPL", Some("Tech"), 10.0), ("XOM", None, 20.0)] {
+        let mut stock = Stock::new(symbol.to_string(), "USD".to_string(), 1.0);
+        stock.id = symbol.to_string();
+        stock.sector = sector.map(str::to_string);
+        portfolio.add_position(stock.id.clone(), shares, None);
+        instruments.insert(stock.id.clone(), Box::new(stock));
+    }
+    let classifications = HashMap::from([(
+        "XOM".to_string(),
+        InstrumentClassification { sector: Some("Energy".to_string()), tags: HashMap::new() },
+    )]);
+
+    let service = PortfolioValuationService::default();
+    let valuator = make_valuator(PricingModelKind::BlackScholes);
+    let value_at = |aapl: f64, xom: f64| {
+        let contexts = HashMap::from([("AAPL".to_string(), context(aapl)), ("XOM".to_string(), context(xom))]);
+        let service = &service;
+        let portfolio = &portfolio;
+        let instruments = &instruments;
+        let valuator = valuator.as_ref();
+        async move { service.value_portfolio_with_contexts(portfolio, instruments, valuator, &contexts).await.unwrap() }
+    };
+    let previous = value_at(100.0, 50.0).await;
+    let current = value_at(110.0, 49.0).await;
+
+    let benchmark = [segment("Tech", 0.3, 0.05), segment("Energy", 0.7, 0.0)];
+    let attribution = service
+        .calculate_portfolio_attribution(&current, &previous, &instruments, &AttributionGrouping::Sector, &classifications, &benchmark)
+        .unwrap();
+
+    let aapl = attribution.position_attributions.iter().find(|p| p.instrument_id == "AAPL").unwrap();
+    assert_eq!(aapl.segment, "Tech");
+    assert!((aapl.weight - 0.5).abs() < 1e-12);
+    assert!((aapl.return_rate - 0.1).abs() < 1e-12);
+    assert!((aapl.contribution - 5.0).abs() < 1e-9);
+    let xom = attribution.position_attributions.iter().find(|p| p.instrument_id == "XOM").unwrap();
+    assert_eq!(xom.segment, "Energy");
+
+    let brinson = &attribution.brinson;
+    assert!((brinson.portfolio_return - (0.5 * 0.1 + 0.5 * -0.02)).abs() < 1e-12);
+    let effects = brinson.allocation + brinson.selection + brinson.interaction;
+    assert!((effects - brinson.excess_return).abs() < 1e-12);
+
+    let by_tag = AttributionGrouping::parse("tag:style").unwrap();
+    assert_eq!(by_tag.segment_of(instruments["AAPL"].as_ref(), None), "Unclassified");
+    assert_eq!(AttributionGrouping::parse("type").unwrap().segment_of(instruments["AAPL"].as_ref(), None), "Stock");
+    assert!(AttributionGrouping::parse("tag:").is_err());
+}
@@ -0,0 +1,130 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use std::collections::{BTreeMap, HashMap};
+use valuation_service::{
+    build_account_nav_series, split_adjusted_closes, Account, CorporateAction, CorporateActionKind, DailyCloses,
+    LedgerEntry, ReliefMethod, TransactionKind,
+};
+
+fn date(m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(2024, m, d).unwrap()
+}
+
+fn buy(id: &str, symbol: &str, quantity: f64, price: f64, m: u32, d: u32) -> LedgerEntry {
+    LedgerEntry {
+        id: id.to_string(),
+        kind: TransactionKind::Buy,
+        symbol: symbol.to_string(),
+        quantity,
+        price: Some(price),
+        amount: None,
+        timestamp: Utc.with_ymd_and_hms(2024, m, d, 15, 0, 0).unwrap(),
+        lot_ids: Vec::new(),
+    }
+}
+
+fn action(symbol: &str, ex_date: NaiveDate, kind: CorporateActionKind) -> CorporateAction {
+    CorporateAction { id: format!("{}-{}", symbol, ex_date), symbol: symbol.to_string(), ex_date, kind }
+}
+
+#[test]
+fn test_split_keeps_cost_basis_and_nav_continuous() {
+    let entries = vec![buy("a", "NVDA", 10.0, 400.0, 5, 1), buy("b", "NVDA", 5.0, 120.0, 6, 10)];
+    let actions = vec![action("NVDA", date(6, 10), CorporateActionKind::Split { ratio: 4.0 })];
+    let account = Account::new(ReliefMethod::Fifo).replay(&entries, &actions, date(12, 31));
+
+    // The split applies before the purchase on the ex-date
+    let lots = account.lots.lots("NVDA");
+    assert_eq!(lots.len(), 2);
+    assert!((lots[0].quantity - 40.0).abs() < 1e-12);
+    assert!((lots[0].cost - 100.0).abs() < 1e-12);
+    assert!((lots[1].quantity - 5.0).abs() < 1e-12);
+    assert!((account.lots.quantity("NVDA") - 45.0).abs() < 1e-12);
+
+    let closes: DailyCloses = HashMap::from([(
+        "NVDA".to_string(),
+        BTreeMap::from([(date(5, 1), 400.0), (date(6, 7), 480.0), (date(6, 10), 120.0)]),
+    )]);
+    let series = build_account_nav_series(account.trades(), account.cash_movements(), &closes, date(6, 10));
+    let values: Vec<f64> = series.iter().map(|p| p.value).collect();
+    assert_eq!(values, vec![4000.0, 4800.0, 45.0 * 120.0]);
+    assert_eq!(series[2].net_flow, 600.0);
+
+    // Adjusted history has no 75% drop across the ex-date
+    let adjusted = split_adjusted_closes(&closes, &actions);
+    assert_eq!(adjusted["NVDA"][&date(6, 7)], 120.0);
+    assert_eq!(adjusted["NVDA"][&date(6, 10)], 120.0);
+}
+
+#[test]
+fn test_symbol_change_dividends_and_spin_off() {
+    let entries = vec![buy("a", "FB", 10.0, 200.0, 1, 5)];
+    let actions = vec![
+        action("FB", date(6, 9), CorporateActionKind::SymbolChange { new_symbol: "META".to_string() }),
+        action("META", date(7, 1), CorporateActionKind::CashDividend { amount_per_share: 0.5 }),
+        action("META", date(8, 1), CorporateActionKind::StockDividend { ratio: 0.1 }),
+        action(
+            "META",
+            date(9, 1),
+            CorporateActionKind::SpinOff { new_symbol: "SPIN".to_string(), ratio: 0.5, cost_allocation: 0.25 },
+        ),
+        // Not yet effective
+        action("META", date(12, 1), CorporateActionKind::Split { ratio: 2.0 }),
+    ];
+    let account = Account::new(ReliefMethod::Fifo).replay(&entries, &actions, date(10, 1));
+
+    assert_eq!(account.lots.quantity("FB"), 0.0);
+    assert!((account.lots.quantity("META") - 11.0).abs() < 1e-12);
+    assert!((account.cash - 5.0).abs() < 1e-12);
+    assert!((account.summary.dividends - 5.0).abs() < 1e-12);
+
+    let meta = &account.lots.lots("META")[0];
+    let spin = &account.lots.lots("SPIN")[0];
+    assert_eq!(meta.id, "a");
+    assert_eq!(spin.id, "a:SPIN");
+    assert!((spin.quantity - 5.5).abs() < 1e-12);
+    // The cost basis of 2000 is split 75/25
+    assert!((meta.cost_basis() - 1500.0).abs() < 1e-9);
+    assert!((spin.cost_basis() - 500.0).abs() < 1e-9);
+
+    // The ticker change moves the lot at cost
+    let moves: Vec<(&str, f64, f64)> = account.trades()[1..3].iter().map(|t| (t.symbol.as_str(), t.quantity, t.price)).collect();
+    assert_eq!(moves, vec![("FB", -10.0, 200.0), ("META", 10.0, 200.0)]);
+}
+
+#[test]
+fn test_corporate_action_json() {
+    let parsed: CorporateAction = serde_json::from_str(
+        r#"{"id":"x","symbol":"GE","ex_date":"2021-08-02","type":"split","ratio":0.125}"#,
+    )
+    .unwrap();
+    assert_eq!(parsed.kind, CorporateActionKind::Split { ratio: 0.125 });
+    assert_eq!(parsed.kind.unit_ratio(), 0.125);
+    assert_eq!(CorporateActionKind::StockDividend { ratio: 0.05 }.unit_ratio(), 1.05);
+}
+
+#[test]
+fn test_ledger_dividend_or_split_duplicating_a_corporate_action() {
+    let dividend = action("AAPL", date(5, 10), CorporateActionKind::CashDividend { amount_per_share: 0.25 });
+    let split = action("AAPL", date(8, 30), CorporateActionKind::Split { ratio: 4.0 });
+    let entry = |id: &str, kind: TransactionKind, m: u32, d: u32| LedgerEntry {
+        kind,
+        quantity: 4.0,
+        amount: Some(2.5),
+        price: None,
+        ..buy(id, "AAPL", 0.0, 0.0, m, d)
+    };
+
+    assert!(dividend.duplicates(&entry("d", TransactionKind::Dividend, 5, 10)));
+    assert!(split.duplicates(&entry("s", TransactionKind::Split, 8, 30)));
+    // Another day, type or symbol is a separate event
+    assert!(!dividend.duplicates(&entry("d", TransactionKind::Dividend, 5, 11)));
+    assert!(!dividend.duplicates(&entry("s", TransactionKind::Split, 5, 10)));
+    assert!(!split.duplicates(&LedgerEntry { symbol: "MSFT".to_string(), ..entry("s", TransactionKind::Split, 8, 30) }));
+
+    let entries = vec![buy("a", "AAPL", 10.0, 180.0, 1, 5), entry("d", TransactionKind::Dividend, 5, 10)];
+    let actions = vec![dividend];
+    let error = Account::new(ReliefMethod::Fifo).replay_validated(&entries, &actions, date(12, 31)).unwrap_err();
+    assert_eq!(error.code(), "duplicate_corporate_action");
+    assert!(error.to_string().contains("AAPL-2024-05-10"));
+    assert!(Account::new(ReliefMethod::Fifo).replay_validated(&entries[..1], &actions, date(12, 31)).is_ok());
+}
@@ -0,0 +1,338 @@
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use valuation_service::{
+    live_entries, realized_pnl_report, Account, AuditAction, LedgerEdit, LedgerEditError, LedgerEntry, LedgerRecord,
+    LedgerViolation, LotBook, ReliefMethod, ReportingPeriod, TransactionKind,
+};
+
+#[test]
+fn test_sells_relieve_oldest_lots_first() {
+    let now = Utc::now();
+    let mut book = LotBook::new();
+    book.open("a".to_string(), "AAPL", 10.0, 100.0, now - Duration::days(2));
+    book.open("b".to_string(), "AAPL", 10.0, 120.0, now - Duration::days(1));
+
+    let closed = book.relieve("AAPL", 15.0, &[]);
+    assert_eq!(closed.iter().map(|c| c.lot_id.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+    let lots = book.lots("AAPL");
+    assert_eq!(lots.len(), 1);
+    assert_eq!(lots[0].id, "b");
+    assert!((lots[0].quantity - 5.0).abs() < 1e-12);
+    assert!((book.average_cost("AAPL").unwrap() - 120.0).abs() < 1e-12);
+
+    // Overselling closes what is open and leaves the symbol flat
+    let closed = book.relieve("AAPL", 8.0, &[]);
+    assert!((closed.iter().map(|c| c.quantity).sum::<f64>() - 5.0).abs() < 1e-12);
+    assert_eq!(book.quantity("AAPL"), 0.0);
+    assert!(book.average_cost("AAPL").is_none());
+    assert_eq!(book.symbols().count(), 0);
+}
+
+#[test]
+fn test_adjustment_replaces_lots() {
+    let now = Utc::now();
+    let mut book = LotBook::new();
+    book.open("a".to_string(), "MSFT", 10.0, 300.0, now);
+    book.open("b".to_string(), "MSFT", 30.0, 340.0, now);
+    assert!((book.average_cost("MSFT").unwrap() - 330.0).abs() < 1e-12);
+
+    book.reset("c".to_string(), "MSFT", 25.0, 310.0, now);
+    let lots = book.lots("MSFT");
+    assert_eq!(lots.len(), 1);
+    assert_eq!(lots[0].id, "c");
+    assert!((lots[0].cost_basis() - 25.0 * 310.0).abs() < 1e-9);
+
+    book.reset("d".to_string(), "MSFT", 0.0, 0.0, now);
+    assert!(book.lots("MSFT").is_empty());
+    assert!(book.into_lots().is_empty());
+}
+
+fn book(method: ReliefMethod) -> LotBook {
+    let now = Utc::now();
+    let mut book = LotBook::new().with_relief_method(method);
+    book.open("a".to_string(), "AAPL", 10.0, 100.0, now - Duration::days(3));
+    book.open("b".to_string(), "AAPL", 10.0, 150.0, now - Duration::days(2));
+    book.open("c".to_string(), "AAPL", 10.0, 120.0, now - Duration::days(1));
+    book
+}
+
+fn open_lots(book: &LotBook) -> Vec<(String, f64)> {
+    book.lots("AAPL").iter().map(|l| (l.id.clone(), l.quantity)).collect()
+}
+
+#[test]
+fn test_relief_methods() {
+    let mut lifo = book(ReliefMethod::Lifo);
+    lifo.relieve("AAPL", 15.0, &[]);
+    assert_eq!(open_lots(&lifo), vec![("a".to_string(), 10.0), ("b".to_string(), 5.0)]);
+
+    let mut hifo = book(ReliefMethod::Hifo);
+    hifo.relieve("AAPL", 15.0, &[]);
+    assert_eq!(open_lots(&hifo), vec![("a".to_string(), 10.0), ("c".to_string(), 5.0)]);
+
+    // Named lots close first, the rest oldest first
+    let mut specific = book(ReliefMethod::SpecificLot);
+    specific.relieve("AAPL", 15.0, &["c".to_string()]);
+    assert_eq!(open_lots(&specific), vec![("a".to_string(), 5.0), ("b".to_string(), 10.0)]);
+
+    // Average cost keeps the remaining units at the average of 123.33
+    let mut average = book(ReliefMethod::AverageCost);
+    average.relieve("AAPL", 15.0, &["c".to_string()]);
+    assert_eq!(open_lots(&average), vec![("b".to_string(), 5.0), ("c".to_string(), 10.0)]);
+    assert!(average.lots("AAPL").iter().all(|l| (l.cost - 370.0 / 3.0).abs() < 1e-9));
+    assert!((average.average_cost("AAPL").unwrap() - 370.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_realized_pnl_by_symbol_and_period() {
+    let at = |m: u32, d: u32| Utc.with_ymd_and_hms(2025, m, d, 15, 0, 0).unwrap();
+    let mut book = LotBook::new();
+    book.open("a".to_string(), "AAPL", 10.0, 100.0, at(1, 2));
+    book.open("b".to_string(), "AAPL", 10.0, 150.0, at(1, 3));
+    book.open("c".to_string(), "MSFT", 5.0, 300.0, at(1, 4));
+
+    book.sell("s1".to_string(), "AAPL", 15.0, 160.0, &[], at(2, 10));
+    book.sell("s2".to_string(), "MSFT", 5.0, 280.0, &[], at(4, 1));
+    // Only the 5 open shares are realized
+    book.sell("s3".to_string(), "AAPL", 8.0, 170.0, &[], at(4, 20));
+
+    let realized = book.realized();
+    assert_eq!(realized.len(), 3);
+    assert!((realized[0].cost_basis - (1000.0 + 750.0)).abs() < 1e-9);
+    assert!((realized[0].realized_pnl - (2400.0 - 1750.0)).abs() < 1e-9);
+    assert_eq!(realized[0].lots.len(), 2);
+    assert!((realized[2].quantity - 5.0).abs() < 1e-12);
+    assert!((book.realized_pnl(Some("AAPL")) - (650.0 + 100.0)).abs() < 1e-9);
+    assert!((book.realized_pnl(None) - (750.0 - 100.0)).abs() < 1e-9);
+
+    let report = realized_pnl_report(realized, None, None, ReportingPeriod::Quarter);
+    assert!((report.realized_pnl - 650.0).abs() < 1e-9);
+    assert_eq!(report.by_symbol.iter().map(|r| r.key.as_str()).collect::<Vec<_>>(), vec!["AAPL", "MSFT"]);
+    assert_eq!(report.by_symbol[0].trades, 2);
+    assert_eq!(report.by_period.iter().map(|r| r.key.as_str()).collect::<Vec<_>>(), vec!["2025-Q1", "2025-Q2"]);
+    assert!((report.by_period[1].realized_pnl - 0.0).abs() < 1e-9);
+
+    let april = realized_pnl_report(realized, NaiveDate::from_ymd_opt(2025, 4, 1), NaiveDate::from_ymd_opt(2025, 4, 30), ReportingPeriod::Month);
+    assert_eq!(april.trades.len(), 2);
+    assert_eq!(april.by_period.len(), 1);
+    assert_eq!(april.by_period[0].key, "2025-04");
+}
+
+fn entry(day: u32, kind: TransactionKind, symbol: &str, quantity: f64, price: Option<f64>, amount: Option<f64>) -> LedgerEntry {
+    LedgerEntry {
+        id: format!("{}-{}", kind.as_str(), day),
+        kind,
+        symbol: symbol.to_string(),
+        quantity,
+        price,
+        amount,
+        timestamp: Utc.with_ymd_and_hms(2025, 5, day, 15, 0, 0).unwrap(),
+        lot_ids: Vec::new(),
+    }
+}
+
+#[test]
+fn test_account_replays_cash_and_corporate_entries() {
+    let mut account = Account::new(ReliefMethod::Fifo);
+    for e in [
+        entry(1, TransactionKind::Deposit, "", 0.0, None, Some(10_000.0)),
+        entry(2, TransactionKind::Buy, "AAPL", 50.0, Some(100.0), None),
+        entry(3, TransactionKind::Split, "AAPL", 2.0, None, None),
+        entry(4, TransactionKind::Dividend, "AAPL", 100.0, Some(1.0), None),
+        entry(5, TransactionKind::Fee, "", 0.0, None, Some(10.0)),
+        entry(5, TransactionKind::TaxWithholding, "", 0.0, None, Some(15.0)),
+        entry(6, TransactionKind::Short, "TSLA", 10.0, Some(200.0), None),
+        entry(7, TransactionKind::Cover, "TSLA", 4.0, Some(150.0), None),
+        entry(8, TransactionKind::Sell, "AAPL", 40.0, Some(60.0), None),
+        entry(9, TransactionKind::Withdrawal, "", 0.0, None, Some(1_000.0)),
+        entry(10, TransactionKind::TransferOut, "AAPL", 10.0, None, None),
+        entry(11, TransactionKind::Interest, "", 0.0, None, Some(5.0)),
+    ] {
+        account.apply(&e);
+    }
+
+    assert!((account.cash - 7_880.0).abs() < 1e-9);
+    assert!((account.lots.quantity("AAPL") - 50.0).abs() < 1e-12);
+    assert!((account.lots.average_cost("AAPL").unwrap() - 50.0).abs() < 1e-12);
+    assert!((account.lots.quantity("TSLA") + 6.0).abs() < 1e-12);
+    // Cover 4 at 150 against a 200 short, sell 40 at 60 against a split-adjusted 50 cost
+    assert!((account.lots.realized_pnl(Some("TSLA")) - 200.0).abs() < 1e-9);
+    assert!((account.lots.realized_pnl(None) - 600.0).abs() < 1e-9);
+    assert!((account.summary.net_income() - 80.0).abs() < 1e-9);
+    assert_eq!(account.summary.implied_deposits, 0.0);
+
+    // Split as a trade at no cost, the transfer out at cost; the rest settle against cash
+    let trades = account.trades();
+    assert_eq!(trades.len(), 6);
+    assert_eq!((trades[1].quantity, trades[1].price), (50.0, 0.0));
+    assert_eq!((trades[5].quantity, trades[5].price), (-10.0, 50.0));
+    let external: f64 = account.cash_movements().iter().filter(|m| m.external).map(|m| m.amount).sum();
+    assert!((external - (10_000.0 - 1_000.0 - 500.0)).abs() < 1e-9);
+}
+
+#[test]
+fn test_account_funds_shortfalls_with_implied_deposits() {
+    let mut account = Account::new(ReliefMethod::Fifo);
+    account.apply(&entry(1, TransactionKind::Buy, "MSFT", 10.0, Some(300.0), None));
+    account.apply(&entry(2, TransactionKind::Sell, "MSFT", 4.0, Some(320.0), None));
+    account.apply(&entry(3, TransactionKind::Fee, "", 0.0, None, Some(1_500.0)));
+
+    assert_eq!(account.cash, 0.0);
+    assert!((account.summary.implied_deposits - (3_000.0 + 220.0)).abs() < 1e-9);
+    let movements = account.cash_movements();
+    assert_eq!(movements.len(), 3);
+    assert!(movements[0].external && (movements[0].amount - 3_000.0).abs() < 1e-9);
+    assert!(!movements[1].external && (movements[1].amount + 1_500.0).abs() < 1e-9);
+    assert!(movements[2].external && (movements[2].amount - 220.0).abs() < 1e-9);
+    assert_eq!(TransactionKind::parse("transfer_in"), Some(TransactionKind::TransferIn));
+    assert_eq!(TransactionKind::parse("SWAP"), None);
+    // Adjustments come from the position endpoints only
+    assert!(TransactionKind::parse("adjust").unwrap().is_internal());
+    assert!(!TransactionKind::Split.is_internal());
+}
+
+#[test]
+fn test_entries_are_validated_before_recording() {
+    let code = |e: LedgerEntry| e.validate().unwrap_err().code();
+    assert_eq!(code(entry(1, TransactionKind::Buy, "AAPL", 10.0, None, None)), "missing_price");
+    assert_eq!(code(entry(1, TransactionKind::Buy, "AAPL", -10.0, Some(100.0), None)), "invalid_quantity");
+    assert_eq!(code(entry(1, TransactionKind::Sell, "", 10.0, Some(100.0), None)), "missing_symbol");
+    assert_eq!(code(entry(1, TransactionKind::Fee, "", 0.0, None, None)), "invalid_amount");
+    let mut named = entry(1, TransactionKind::Buy, "AAPL", 1.0, Some(100.0), None);
+    named.lot_ids = vec!["a".to_string()];
+    assert_eq!(code(named), "lot_ids_not_allowed");
+    assert!(entry(1, TransactionKind::Deposit, "", 0.0, None, Some(500.0)).validate().is_ok());
+    assert!(entry(1, TransactionKind::Adjust, "AAPL", 0.0, None, None).validate().is_ok());
+
+    let mut account = Account::new(ReliefMethod::Fifo);
+    account.apply(&entry(2, TransactionKind::Buy, "AAPL", 10.0, Some(100.0), None));
+    let oversell = account.validate(&entry(3, TransactionKind::Sell, "AAPL", 15.0, Some(110.0), None)).unwrap_err();
+    assert_eq!(oversell, LedgerViolation::Oversell { requested: 15.0, held: 10.0 });
+    assert!(oversell.is_conflict());
+    assert_eq!(
+        account.validate(&entry(3, TransactionKind::Short, "AAPL", 1.0, Some(110.0), None)),
+        Err(LedgerViolation::ShortNotAllowed)
+    );
+    assert_eq!(account.validate(&entry(3, TransactionKind::Cover, "AAPL", 1.0, Some(110.0), None)).unwrap_err().code(), "overcover");
+    let mut unknown_lot = entry(3, TransactionKind::Sell, "AAPL", 1.0, Some(110.0), None);
+    unknown_lot.lot_ids = vec!["missing".to_string()];
+    assert_eq!(account.validate(&unknown_lot), Err(LedgerViolation::LotNotOpen("missing".to_string())));
+}
+
+#[test]
+fn test_oversells_open_shorts_when_allowed() {
+    let mut account = Account::new(ReliefMethod::Fifo).with_short_sales(true);
+    account.apply(&entry(1, TransactionKind::Buy, "TSLA", 10.0, Some(200.0), None));
+    let sell = entry(2, TransactionKind::Sell, "TSLA", 15.0, Some(220.0), None);
+    assert!(account.validate(&sell).is_ok());
+    account.apply(&sell);
+    assert!((account.lots.quantity("TSLA") + 5.0).abs() < 1e-12);
+    assert!((account.short_quantity("TSLA") - 5.0).abs() < 1e-12);
+    assert!((account.lots.realized_pnl(None) - 200.0).abs() < 1e-9);
+
+    // A purchase covers the short first
+    account.apply(&entry(3, TransactionKind::Buy, "TSLA", 8.0, Some(210.0), None));
+    assert!((account.long_quantity("TSLA") - 3.0).abs() < 1e-12);
+    assert_eq!(account.short_quantity("TSLA"), 0.0);
+    assert!((account.lots.realized_pnl(None) - (200.0 + 5.0 * 10.0)).abs() < 1e-9);
+    assert_eq!(account.lots.realized().len(), 2);
+
+    // A sale with nothing held only opens a short: no realized trade is recorded
+    let cash = account.cash;
+    account.apply(&entry(4, TransactionKind::Sell, "NFLX", 4.0, Some(600.0), None));
+    assert!((account.short_quantity("NFLX") - 4.0).abs() < 1e-12);
+    assert!((account.cash - cash - 2_400.0).abs() < 1e-9);
+    assert_eq!(account.lots.realized().len(), 2);
+
+    // Nor does a cover with no short open
+    let mut book = LotBook::new();
+    assert_eq!(book.cover("c".to_string(), "NFLX", 4.0, 590.0, &[], Utc::now()), 0.0);
+    assert_eq!(book.sell("s".to_string(), "NFLX", 4.0, 590.0, &[], Utc::now()), 0.0);
+    assert!(book.realized().is_empty());
+}
+
+fn records(entries: Vec<LedgerEntry>) -> Vec<LedgerRecord> {
+    entries.into_iter().map(|entry| LedgerRecord { entry, deleted_at: None }).collect()
+}
+
+fn replay_checked(entries: &[LedgerEntry]) -> Result<Account, LedgerEditError> {
+    Account::new(ReliefMethod::Fifo).replay_validated(entries, &[], NaiveDate::from_ymd_opt(2025, 12, 31).unwrap())
+}
+
+#[test]
+fn test_edits_revalidate_the_whole_ledger() {
+    let stored = records(vec![
+        entry(1, TransactionKind::Buy, "AAPL", 10.0, Some(100.0), None),
+        entry(5, TransactionKind::Sell, "AAPL", 8.0, Some(120.0), None),
+    ]);
+
+    // Shrinking the purchase leaves the later sale oversold, which names the sale
+    let mut smaller = stored[0].entry.clone();
+    smaller.quantity = 5.0;
+    let edited = LedgerEdit::Replace(smaller).apply(&stored).unwrap();
+    assert_eq!(edited.action, AuditAction::Update);
+    assert_eq!(edited.before.as_ref().unwrap().quantity, 10.0);
+    assert_eq!(edited.after.as_ref().unwrap().quantity, 5.0);
+    assert_eq!(
+        replay_checked(&edited.entries).unwrap_err(),
+        LedgerEditError::Violation {
+            transaction_id: "SELL-5".to_string(),
+            violation: LedgerViolation::Oversell { requested: 8.0, held: 5.0 },
+        }
+    );
+
+    // So does deleting it, or a backdated sale of the same units
+    let deleted = LedgerEdit::Delete("BUY-1".to_string()).apply(&stored).unwrap();
+    assert_eq!(deleted.action, AuditAction::Delete);
+    assert!(deleted.after.is_none());
+    assert_eq!(replay_checked(&deleted.entries).unwrap_err().code(), "oversell");
+    let backdated = LedgerEdit::Insert(entry(3, TransactionKind::Sell, "AAPL", 5.0, Some(110.0), None)).apply(&stored).unwrap();
+    assert_eq!(backdated.action, AuditAction::Create);
+    assert!(backdated.before.is_none());
+    assert_eq!(backdated.entries.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["BUY-1", "SELL-3", "SELL-5"]);
+    match replay_checked(&backdated.entries).unwrap_err() {
+        LedgerEditError::Violation { transaction_id, .. } => assert_eq!(transaction_id, "SELL-5"),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    // Moving the sale before the purchase is caught on the sale itself
+    let mut early = stored[1].entry.clone();
+    early.timestamp = Utc.with_ymd_and_hms(2025, 4, 30, 15, 0, 0).unwrap();
+    let moved = LedgerEdit::Replace(early).apply(&stored).unwrap();
+    assert_eq!(moved.changed_from(), NaiveDate::from_ymd_opt(2025, 4, 30));
+    assert_eq!(replay_checked(&moved.entries).unwrap_err().code(), "oversell");
+
+    // A valid edit replays to the edited holdings
+    let mut bigger = stored[0].entry.clone();
+    bigger.quantity = 12.0;
+    let edited = LedgerEdit::Replace(bigger).apply(&stored).unwrap();
+    assert_eq!(edited.changed_from(), NaiveDate::from_ymd_opt(2025, 5, 1));
+    let account = replay_checked(&edited.entries).unwrap();
+    assert!((account.lots.quantity("AAPL") - 4.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_deleted_entries_leave_the_ledger() {
+    let mut stored = records(vec![
+        entry(1, TransactionKind::Deposit, "", 0.0, None, Some(1_000.0)),
+        entry(2, TransactionKind::Buy, "MSFT", 2.0, Some(300.0), None),
+    ]);
+    stored[1].deleted_at = Some(Utc.with_ymd_and_hms(2025, 5, 3, 9, 0, 0).unwrap());
+    assert!(stored[1].is_deleted());
+
+    let live = live_entries(&stored);
+    assert_eq!(live.len(), 1);
+    let account = replay_checked(&live).unwrap();
+    assert_eq!(account.lots.quantity("MSFT"), 0.0);
+    assert!((account.cash - 1_000.0).abs() < 1e-9);
+
+    // A deleted entry cannot be edited or deleted again; an unknown one is not found
+    let mut edit = stored[1].entry.clone();
+    edit.quantity = 1.0;
+    assert_eq!(LedgerEdit::Replace(edit).apply(&stored).unwrap_err(), LedgerEditError::Deleted("BUY-2".to_string()));
+    let again = LedgerEdit::Delete("BUY-2".to_string()).apply(&stored).unwrap_err();
+    assert_eq!(again.code(), "transaction_deleted");
+    let missing = LedgerEdit::Delete("nope".to_string()).apply(&stored).unwrap_err();
+    assert_eq!(missing, LedgerEditError::NotFound("nope".to_string()));
+    assert_eq!(missing.code(), "transaction_not_found");
+    assert_eq!(serde_json::to_value(AuditAction::Delete).unwrap(), "delete");
+}
@@ -0,0 +1,150 @@
+use nalgebra as na;
+use std::collections::HashMap;
+use valuation_service::{
+    OptimizationAsset, OptimizationConstraints, OptimizationObjective, OptimizedPortfolio, PortfolioOptimizer,
+    WeightBounds,
+};
+
+fn universe() -> (Vec<OptimizationAsset>, na::DMatrix<f64>) {
+    let assets = [("AAPL", 0.12, "Tech", 0.4), ("MSFT", 0.10, "Tech", 0.3), ("XOM", 0.07, "Energy", 0.2), ("JNJ", 0.05, "Health", 0.1)]
+        .iter()
+        .map(|(symbol, expected_return, sector, current_weight)| OptimizationAsset {
+            symbol: symbol.to_string(),
+            expected_return: *expected_return,
+            sector: Some(sector.to_string()),
+            current_weight: *current_weight,
+        })
+        .collect();
+    let vols = [0.30, 0.25, 0.20, 0.15];
+    let correlation = na::DMatrix::from_row_slice(4, 4, &[
+        1.0, 0.6, 0.3, 0.2,
+        0.6, 1.0, 0.3, 0.2,
+        0.3, 0.3, 1.0, 0.1,
+        0.2, 0.2, 0.1, 1.0,
+    ]);
+    let covariance = na::DMatrix::from_fn(4, 4, |i, j| correlation[(i, j)] * vols[i] * vols[j]);
+    (assets, covariance)
+}
+
+fn weights(result: &OptimizedPortfolio) -> na::DVector<f64> {
+    na::DVector::from_iterator(result.weights.len(), result.weights.iter().map(|w| w.weight))
+}
+
+fn unconstrained() -> OptimizationConstraints {
+    OptimizationConstraints { long_only: false, min_weight: Some(-5.0), max_weight: Some(5.0), ..Default::default() }
+}
+
+/// Closed-form solution of min w'Sw subject to sum(w) = 1 along direction S^-1 v
+fn normalized_inverse(covariance: &na::DMatrix<f64>, v: &na::DVector<f64>) -> na::DVector<f64> {
+    let w = covariance.clone().try_inverse().unwrap() * v;
+    let total = w.sum();
+    w / total
+}
+
+#[test]
+fn test_closed_form_objectives_without_binding_constraints() {
+    let (assets, covariance) = universe();
+    let mu = na::DVector::from_iterator(4, assets.iter().map(|a| a.expected_return));
+    let optimizer = PortfolioOptimizer::new(assets, covariance.clone())
+        .unwrap()
+        .with_constraints(unconstrained())
+        .with_risk_free_rate(0.03);
+
+    let min_variance = optimizer.optimize(&OptimizationObjective::MinimumVariance).unwrap();
+    let expected = normalized_inverse(&covariance, &na::DVector::from_element(4, 1.0));
+    assert!((weights(&min_variance) - &expected).amax() < 1e-5);
+    assert!((weights(&min_variance).sum() - 1.0).abs() < 1e-8);
+
+    let tangency = optimizer.optimize(&OptimizationObjective::MaximumSharpe).unwrap();
+    let expected = normalized_inverse(&covariance, &mu.add_scalar(-0.03));
+    assert!((weights(&tangency) - &expected).amax() < 1e-4);
+    assert!(tangency.sharpe_ratio.unwrap() >= min_variance.sharpe_ratio.unwrap());
+
+    let diversified = optimizer.optimize(&OptimizationObjective::MaximumDiversification).unwrap();
+    let vols = covariance.diagonal().map(|v| v.sqrt());
+    let expected = normalized_inverse(&covariance, &vols);
+    assert!((weights(&diversified) - &expected).amax() < 1e-4);
+    assert!(diversified.diversification_ratio > min_variance.diversification_ratio);
+
+    let target = optimizer.optimize(&OptimizationObjective::TargetReturn { target_return: 0.09 }).unwrap();
+    assert!((target.expected_return - 0.09).abs() < 1e-7);
+    assert!(target.volatility >= min_variance.volatility);
+}
+
+#[test]
+fn test_risk_parity_equalizes_contributions() {
+    let (assets, covariance) = universe();
+    let optimizer = PortfolioOptimizer::new(assets, covariance).unwrap();
+    let result = optimizer.optimize(&OptimizationObjective::RiskParity).unwrap();
+    for w in &result.weights {
+        assert!((w.risk_contribution - 0.25).abs() < 1e-8, "{} contributes {}", w.symbol, w.risk_contribution);
+        assert!(w.weight > 0.0);
+    }
+    assert!((weights(&result).sum() - 1.0).abs() < 1e-10);
+
+    // A binding cap on JNJ: constrained risk budgeting keeps every asset and spreads the rest
+    let mut bounds = HashMap::new();
+    bounds.insert("JNJ".to_string(), WeightBounds { min: None, max: Some(0.2) });
+    let capped = PortfolioOptimizer::new(universe().0, universe().1)
+        .unwrap()
+        .with_constraints(OptimizationConstraints { bounds, ..Default::default() })
+        .optimize(&OptimizationObjective::RiskParity)
+        .unwrap();
+    let jnj = capped.weights.iter().find(|w| w.symbol == "JNJ").unwrap();
+    assert!((jnj.weight - 0.2).abs() < 1e-6);
+    assert!((weights(&capped).sum() - 1.0).abs() < 1e-8);
+    let others: Vec<f64> = capped.weights.iter().filter(|w| w.symbol != "JNJ").map(|w| w.risk_contribution).collect();
+    assert!(others.iter().all(|c| *c > 0.25 && *c < 0.4), "{:?}", others);
+
+    let shorting = PortfolioOptimizer::new(universe().0, universe().1).unwrap().with_constraints(unconstrained());
+    assert!(shorting.optimize(&OptimizationObjective::RiskParity).is_err());
+}
+
+#[test]
+fn test_long_only_sector_and_turnover_constraints() {
+    let (assets, covariance) = universe();
+    let mut sector_caps = HashMap::new();
+    sector_caps.insert("Tech".to_string(), 0.3);
+    let constraints = OptimizationConstraints { max_weight: Some(0.5), sector_caps, ..Default::default() };
+    let optimizer = PortfolioOptimizer::new(assets.clone(), covariance.clone()).unwrap().with_constraints(constraints);
+
+    let result = optimizer.optimize(&OptimizationObjective::MaximumSharpe).unwrap();
+    let w = weights(&result);
+    assert!(w.iter().all(|w| *w >= -1e-8 && *w <= 0.5 + 1e-8));
+    assert!(w[0] + w[1] <= 0.3 + 1e-6);
+    assert!((w.sum() - 1.0).abs() < 1e-6);
+
+    // Turnover limits how far the optimizer can move from the current 40/30/20/10 book
+    let limited = PortfolioOptimizer::new(assets, covariance)
+        .unwrap()
+        .with_constraints(OptimizationConstraints { max_turnover: Some(0.1), ..Default::default() });
+    let result = limited.optimize(&OptimizationObjective::MinimumVariance).unwrap();
+    assert!(result.turnover <= 0.1 + 1e-6);
+    assert!(result.turnover > 0.09);
+    let free = limited.with_constraints(OptimizationConstraints::default());
+    assert!(free.optimize(&OptimizationObjective::MinimumVariance).unwrap().volatility < result.volatility);
+}
+
+#[test]
+fn test_efficient_frontier_and_infeasible_constraints() {
+    let (assets, covariance) = universe();
+    let optimizer = PortfolioOptimizer::new(assets.clone(), covariance.clone()).unwrap();
+    let frontier = optimizer.efficient_frontier(8).unwrap();
+    assert_eq!(frontier.len(), 8);
+    let min_variance = optimizer.optimize(&OptimizationObjective::MinimumVariance).unwrap();
+    assert!((frontier[0].volatility - min_variance.volatility).abs() < 1e-8);
+    // Long-only: the top of the frontier is fully in the highest-return asset
+    assert!((frontier[7].expected_return - 0.12).abs() < 1e-5);
+    for pair in frontier.windows(2) {
+        assert!(pair[1].expected_return > pair[0].expected_return);
+        assert!(pair[1].volatility >= pair[0].volatility - 1e-9);
+    }
+    assert!(frontier.iter().all(|p| (p.weights.values().sum::<f64>() - 1.0).abs() < 1e-6));
+
+    let unattainable = optimizer.optimize(&OptimizationObjective::TargetReturn { target_return: 0.2 });
+    assert!(unattainable.is_err());
+    let too_tight = PortfolioOptimizer::new(assets, covariance)
+        .unwrap()
+        .with_constraints(OptimizationConstraints { max_weight: Some(0.2), ..Default::default() });
+    assert!(too_tight.optimize(&OptimizationObjective::MinimumVariance).is_err());
+}
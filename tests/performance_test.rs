@@ -0,0 +1,216 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use std::collections::{BTreeMap, HashMap};
+use valuation_service::{
+    build_account_nav_series, build_nav_series, snapshots_from_ledger, xirr, Benchmark, BenchmarkAnalyzer, CashMovement, DailyCloses, EodSchedule, LedgerTrade,
+    NavPoint, PerformanceEngine,
+};
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[test]
+fn test_nav_series_from_ledger() {
+    let trades = vec![
+        LedgerTrade { date: date(2024, 3, 1), symbol: "AAPL".to_string(), quantity: 10.0, price: 100.0 },
+        LedgerTrade { date: date(2024, 3, 5), symbol: "AAPL".to_string(), quantity: -4.0, price: 105.0 },
+        // Oversell is capped at the 6 shares left
+        LedgerTrade { date: date(2024, 3, 6), symbol: "AAPL".to_string(), quantity: -10.0, price: 90.0 },
+    ];
+    let closes: DailyCloses = HashMap::from([(
+        "AAPL".to_string(),
+        BTreeMap::from([(date(2024, 2, 28), 95.0), (date(2024, 3, 1), 102.0), (date(2024, 3, 4), 110.0), (date(2024, 3, 5), 104.0)]),
+    )]);
+
+    let series = build_nav_series(&trades, &closes, date(2024, 3, 31));
+    let dates: Vec<NaiveDate> = series.iter().map(|p| p.date).collect();
+    assert_eq!(dates, vec![date(2024, 3, 1), date(2024, 3, 4), date(2024, 3, 5), date(2024, 3, 6)]);
+    assert_eq!(series[0].value, 1020.0);
+    assert_eq!(series[0].net_flow, 1000.0);
+    assert_eq!(series[2].value, 6.0 * 104.0);
+    assert_eq!(series[2].net_flow, -420.0);
+    assert_eq!(series[3].value, 0.0);
+    assert_eq!(series[3].net_flow, -540.0);
+}
+
+#[test]
+fn test_account_nav_series_includes_cash() {
+    let trades = vec![LedgerTrade { date: date(2024, 3, 1), symbol: "AAPL".to_string(), quantity: 10.0, price: 100.0 }];
+    let cash = vec![
+        CashMovement { date: date(2024, 3, 1), amount: 2000.0, external: true },
+        // A dividend stays in the account: income, not a flow
+        CashMovement { date: date(2024, 3, 4), amount: 50.0, external: false },
+        CashMovement { date: date(2024, 3, 5), amount: -500.0, external: true },
+    ];
+    let closes: DailyCloses = HashMap::from([(
+        "AAPL".to_string(),
+        BTreeMap::from([(date(2024, 3, 1), 100.0), (date(2024, 3, 4), 110.0)]),
+    )]);
+
+    let series = build_account_nav_series(&trades, &cash, &closes, date(2024, 3, 31));
+    assert_eq!(series.len(), 3);
+    assert_eq!((series[0].value, series[0].net_flow), (2000.0, 2000.0));
+    assert_eq!((series[1].value, series[1].net_flow), (1100.0 + 1050.0, 0.0));
+    assert_eq!((series[2].value, series[2].net_flow), (1100.0 + 550.0, -500.0));
+}
+
+#[test]
+fn test_time_and_money_weighted_returns() {
+    let series = vec![
+        NavPoint { date: date(2024, 1, 2), value: 1_000.0, net_flow: 1_000.0 },
+        NavPoint { date: date(2024, 1, 3), value: 1_100.0, net_flow: 0.0 },
+        // Doubling the investment right after a gain
+        NavPoint { date: date(2024, 1, 4), value: 2_089.5, net_flow: 1_000.0 },
+        NavPoint { date: date(2024, 2, 1), value: 2_298.45, net_flow: 0.0 },
+    ];
+    let report = PerformanceEngine::new(0.0).analyze(&series, None, None).unwrap();
+
+    // Daily returns 0%, +10%, -0.5%, +10%: TWR ignores the timing of the flow
+    let expected = 1.1 * 0.995 * 1.1 - 1.0;
+    assert!((report.time_weighted_return - expected).abs() < 1e-12);
+    assert!((report.total_return - 298.45).abs() < 1e-9);
+    assert!((report.max_drawdown - 0.005).abs() < 1e-12);
+    assert!(report.calmar_ratio.unwrap() > 0.0);
+    assert!(report.money_weighted_return.unwrap() > 0.0);
+    assert_eq!(report.daily_returns.len(), 4);
+
+    let periods = &report.periods;
+    assert!((periods.mtd.unwrap() - 0.1).abs() < 1e-12);
+    assert!((periods.ytd.unwrap() - expected).abs() < 1e-12);
+    assert_eq!(periods.ytd, periods.inception);
+
+    // A range starting after the first close excludes that day's return
+    let ranged = PerformanceEngine::new(0.0).analyze(&series, Some(date(2024, 1, 3)), None).unwrap();
+    assert_eq!(ranged.start_value, 1_100.0);
+    assert!((ranged.time_weighted_return - (0.995 * 1.1 - 1.0)).abs() < 1e-12);
+    assert!((ranged.net_flows - 1_000.0).abs() < 1e-12);
+}
+
+#[test]
+fn test_xirr() {
+    let flows = [(date(2023, 1, 1), -100.0), (date(2024, 1, 1), 110.0)];
+    assert!((xirr(&flows).unwrap() - 0.1).abs() < 1e-8);
+
+    let losing = [(date(2023, 1, 1), -100.0), (date(2023, 7, 2), -100.0), (date(2024, 1, 1), 150.0)];
+    let rate = xirr(&losing).unwrap();
+    assert!(rate < 0.0);
+    let npv = -100.0 - 100.0 / (1.0 + rate).powf(182.0 / 365.0) + 150.0 / (1.0 + rate);
+    assert!(npv.abs() < 1e-6);
+
+    assert!(xirr(&[(date(2023, 1, 1), 100.0)]).is_none());
+}
+
+#[test]
+fn test_snapshots_replay_fifo_lots() {
+    let trades = vec![
+        LedgerTrade { date: date(2024, 3, 1), symbol: "AAPL".to_string(), quantity: 10.0, price: 100.0 },
+        LedgerTrade { date: date(2024, 3, 2), symbol: "AAPL".to_string(), quantity: 10.0, price: 120.0 },
+        LedgerTrade { date: date(2024, 3, 5), symbol: "AAPL".to_string(), quantity: -15.0, price: 130.0 },
+    ];
+    let closes: DailyCloses = HashMap::from([(
+        "AAPL".to_string(),
+        BTreeMap::from([(date(2024, 3, 4), 125.0), (date(2024, 3, 5), 131.0)]),
+    )]);
+    let dates = EodSchedule::business_days(date(2024, 3, 1), date(2024, 3, 5));
+    assert_eq!(dates, vec![date(2024, 3, 1), date(2024, 3, 4), date(2024, 3, 5)]);
+
+    let snapshots = snapshots_from_ledger("default", &trades, &closes, &dates);
+    // Friday: no close yet, priced at the trade
+    assert_eq!(snapshots[0].total_value, 1_000.0);
+    assert_eq!(snapshots[0].net_flow, 1_000.0);
+    // Monday picks up the weekend purchase as a flow
+    assert_eq!(snapshots[1].net_flow, 1_200.0);
+    assert_eq!(snapshots[1].cost_basis, 2_200.0);
+    assert_eq!(snapshots[1].total_value, 2_500.0);
+    // FIFO: the 15 sold shares are the 10 @ 100 and 5 @ 120
+    let last = &snapshots[2];
+    assert_eq!(last.positions[0].quantity, 5.0);
+    assert_eq!(last.cost_basis, 600.0);
+    assert_eq!(last.unrealized_pnl, 5.0 * 131.0 - 600.0);
+    assert_eq!(last.net_flow, -15.0 * 130.0);
+
+    // Starting mid-history, earlier trades only build the opening book
+    let later = snapshots_from_ledger("default", &trades, &closes, &dates[2..]);
+    assert_eq!(later[0].net_flow, -15.0 * 130.0);
+    assert_eq!(later[0].cost_basis, 600.0);
+}
+
+#[test]
+fn test_eod_schedule_cutoff_and_timezone() {
+    let schedule = EodSchedule::parse("16:00", "America/New_York").unwrap();
+    assert!(EodSchedule::parse("25:00", "America/New_York").is_err());
+    assert!(EodSchedule::parse("16:00", "Mars/Olympus").is_err());
+
+    // Summer: 16:00 New York is 20:00 UTC
+    assert_eq!(schedule.cutoff_at(date(2024, 7, 1)), Utc.with_ymd_and_hms(2024, 7, 1, 20, 0, 0).unwrap());
+    // Winter: 21:00 UTC
+    assert_eq!(schedule.cutoff_at(date(2024, 1, 8)), Utc.with_ymd_and_hms(2024, 1, 8, 21, 0, 0).unwrap());
+
+    // Monday before the cut-off: last completed day is the previous Friday
+    let monday_morning = Utc.with_ymd_and_hms(2024, 7, 1, 14, 0, 0).unwrap();
+    assert_eq!(schedule.last_completed(monday_morning), date(2024, 6, 28));
+    assert_eq!(schedule.next_run(monday_morning), Utc.with_ymd_and_hms(2024, 7, 1, 20, 0, 0).unwrap());
+
+    // Friday after the cut-off: next run is Monday
+    let friday_evening = Utc.with_ymd_and_hms(2024, 7, 5, 22, 0, 0).unwrap();
+    assert_eq!(schedule.last_completed(friday_evening), date(2024, 7, 5));
+    assert_eq!(schedule.next_run(friday_evening), Utc.with_ymd_and_hms(2024, 7, 8, 20, 0, 0).unwrap());
+}
+
+#[test]
+fn test_benchmark_basket_returns() {
+    assert!(Benchmark::parse("SPY:abc").is_err());
+    assert!(Benchmark::parse("SPY:-1").is_err());
+    let basket = Benchmark::parse("spy:3, agg:1").unwrap();
+    assert_eq!(basket.symbols(), vec!["SPY".to_string(), "AGG".to_string()]);
+    assert!((basket.components[0].weight - 0.75).abs() < 1e-12);
+
+    let closes: DailyCloses = HashMap::from([
+        ("SPY".to_string(), BTreeMap::from([(date(2024, 3, 1), 100.0), (date(2024, 3, 4), 110.0), (date(2024, 3, 5), 99.0)])),
+        // No AGG close on 3/4: the basket return spans 3/1 to 3/5
+        ("AGG".to_string(), BTreeMap::from([(date(2024, 3, 1), 50.0), (date(2024, 3, 5), 51.0)])),
+    ]);
+    let returns = basket.daily_returns(&closes);
+    assert_eq!(returns.len(), 1);
+    assert_eq!(returns[0].0, date(2024, 3, 5));
+    assert!((returns[0].1 - (0.75 * -0.01 + 0.25 * 0.02)).abs() < 1e-12);
+
+    let spy = Benchmark::symbol("SPY").daily_returns(&closes);
+    assert_eq!(spy.len(), 2);
+    assert!((spy[0].1 - 0.1).abs() < 1e-12);
+}
+
+#[test]
+fn test_benchmark_statistics() {
+    let start = date(2024, 1, 1);
+    let benchmark: Vec<(NaiveDate, f64)> = (0..120)
+        .map(|i| (start + chrono::Duration::days(i), 0.01 * ((i as f64) * 0.7).sin()))
+        .collect();
+    // Levered 1.5x with a constant daily edge; one extra portfolio day without a benchmark return
+    let mut portfolio: Vec<(NaiveDate, f64)> = benchmark.iter().map(|(d, r)| (*d, 1.5 * r + 0.0002)).collect();
+    portfolio.push((date(2025, 1, 1), 0.05));
+
+    let analyzer = BenchmarkAnalyzer::new(0.0);
+    let stats = analyzer.analyze(&portfolio, &benchmark).unwrap();
+    assert_eq!(stats.observations, 120);
+    assert!((stats.beta.unwrap() - 1.5).abs() < 1e-9);
+    assert!((stats.r_squared.unwrap() - 1.0).abs() < 1e-9);
+    let mean_b = benchmark.iter().map(|(_, r)| r).sum::<f64>() / 120.0;
+    assert!((stats.alpha.unwrap() - 0.0002 * 252.0).abs() < 1e-9);
+    assert!(stats.tracking_error.unwrap() > 0.0);
+    let mean_active = 0.5 * mean_b + 0.0002;
+    assert!((stats.information_ratio.unwrap() - mean_active * 252.0 / stats.tracking_error.unwrap()).abs() < 1e-9);
+    assert!(stats.up_capture.unwrap() > 1.5);
+    assert!(stats.down_capture.unwrap() < 1.5);
+
+    // A risk-free rate shifts Jensen's alpha by (beta - 1) * rf
+    let with_rf = BenchmarkAnalyzer::new(0.05).analyze(&portfolio, &benchmark).unwrap();
+    assert!((with_rf.alpha.unwrap() - (0.0002 * 252.0 + 0.5 * 0.05)).abs() < 1e-9);
+
+    let rolling = analyzer.rolling(&portfolio, &benchmark, 60).unwrap();
+    assert_eq!(rolling.len(), 61);
+    assert_eq!(rolling[0].end_date, benchmark[59].0);
+    assert!(rolling.iter().all(|s| (s.beta.unwrap() - 1.5).abs() < 1e-9));
+    assert!(analyzer.rolling(&portfolio, &benchmark, 1).is_err());
+    assert!(analyzer.analyze(&portfolio[..0], &benchmark).is_err());
+}
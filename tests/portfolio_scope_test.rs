@@ -0,0 +1,91 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use valuation_service::{
+    check_deletable, validate_portfolio_id, LedgerEditError, LedgerEntry, LedgerViolation, PortfolioScope,
+    PortfolioScopeError, PortfolioSettings, ReliefMethod, TransactionKind, DEFAULT_PORTFOLIO_ID,
+};
+
+fn trade(id: &str, kind: TransactionKind, quantity: f64, price: f64, day: u32) -> LedgerEntry {
+    LedgerEntry {
+        id: id.to_string(),
+        kind,
+        symbol: "AAPL".to_string(),
+        quantity,
+        price: Some(price),
+        amount: None,
+        timestamp: Utc.with_ymd_and_hms(2025, 3, day, 15, 0, 0).unwrap(),
+        lot_ids: Vec::new(),
+    }
+}
+
+#[test]
+fn test_unscoped_routes_use_the_default_portfolio() {
+    let scope = PortfolioScope::from_path(None);
+    assert_eq!(scope, PortfolioScope::Default);
+    assert_eq!(scope.lookup(), None);
+    assert_eq!(scope.resolve(false).unwrap(), DEFAULT_PORTFOLIO_ID);
+
+    let scope = PortfolioScope::from_path(Some("client-042"));
+    assert_eq!(scope.lookup(), Some("client-042"));
+    assert_eq!(scope.clone().resolve(true).unwrap(), "client-042");
+    let missing = scope.resolve(false).unwrap_err();
+    assert_eq!(missing, PortfolioScopeError::NotFound("client-042".to_string()));
+    assert_eq!(missing.code(), "portfolio_not_found");
+}
+
+#[test]
+fn test_portfolio_ids_and_deletion() {
+    assert!(validate_portfolio_id("client-042_b").is_ok());
+    for id in ["", "has space", "slash/id", &"x".repeat(65)] {
+        assert_eq!(validate_portfolio_id(id).unwrap_err().code(), "invalid_portfolio_id");
+    }
+    assert_eq!(check_deletable(DEFAULT_PORTFOLIO_ID), Err(PortfolioScopeError::DefaultNotDeletable));
+    assert!(check_deletable("client-042").is_ok());
+}
+
+#[test]
+fn test_each_portfolio_replays_its_own_ledger_with_its_settings() {
+    let fifo = PortfolioSettings::default();
+    let lifo = PortfolioSettings { lot_relief: ReliefMethod::Lifo, allow_short: true };
+    let shared = [trade("a", TransactionKind::Buy, 10.0, 100.0, 3), trade("b", TransactionKind::Buy, 10.0, 120.0, 4)];
+
+    let mut first = fifo.account();
+    let mut second = lifo.account();
+    for entry in &shared {
+        first.apply(entry);
+        second.apply(entry);
+    }
+    // Only the second portfolio records the sale
+    second.apply(&trade("c", TransactionKind::Sell, 5.0, 130.0, 5));
+
+    assert_eq!(first.lots.quantity("AAPL"), 20.0);
+    assert_eq!(second.lots.quantity("AAPL"), 15.0);
+    assert_eq!(second.lots.lots("AAPL").iter().map(|l| (l.id.as_str(), l.quantity)).collect::<Vec<_>>(), vec![("a", 10.0), ("b", 5.0)]);
+
+    // Short sales follow each portfolio's setting
+    let oversell = trade("d", TransactionKind::Sell, 25.0, 130.0, 6);
+    assert_eq!(first.validate(&oversell), Err(LedgerViolation::Oversell { requested: 25.0, held: 20.0 }));
+    assert!(second.validate(&oversell).is_ok());
+}
+
+#[test]
+fn test_settings_changes_must_keep_the_ledger_valid() {
+    let through = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+    let shorting = PortfolioSettings { lot_relief: ReliefMethod::Fifo, allow_short: true };
+    let ledger = [trade("a", TransactionKind::Buy, 10.0, 100.0, 3), trade("b", TransactionKind::Short, 5.0, 120.0, 4)];
+    assert!(shorting.revalidate(&ledger, &[], through).is_ok());
+
+    // Turning short sales off with a short open is refused, naming the short sale
+    let long_only = PortfolioSettings { allow_short: false, ..shorting };
+    assert_eq!(
+        long_only.revalidate(&ledger, &[], through).unwrap_err(),
+        LedgerEditError::Violation { transaction_id: "b".to_string(), violation: LedgerViolation::ShortNotAllowed }
+    );
+
+    // So is average cost under a sale of specific lots
+    let mut sale = trade("c", TransactionKind::Sell, 5.0, 130.0, 5);
+    sale.lot_ids = vec!["a".to_string()];
+    let ledger = [trade("a", TransactionKind::Buy, 10.0, 100.0, 3), sale];
+    assert!(shorting.revalidate(&ledger, &[], through).is_ok());
+    let average = PortfolioSettings { lot_relief: ReliefMethod::AverageCost, ..shorting };
+    assert_eq!(average.revalidate(&ledger, &[], through).unwrap_err().code(), "lot_ids_with_average_cost");
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use valuation_service::{
+    normalize_weights, LotSelection, RebalanceEngine, RebalanceHolding, RebalanceOptions, TaxLot, TradeSide,
+};
+
+fn holding(symbol: &str, price: f64, lots: &[(f64, f64)]) -> RebalanceHolding {
+    RebalanceHolding {
+        symbol: symbol.to_string(),
+        price,
+        lots: lots
+            .iter()
+            .enumerate()
+            .map(|(i, (quantity, cost_basis))| TaxLot { id: format!("{}-{}", symbol, i), quantity: *quantity, cost_basis: *cost_basis })
+            .collect(),
+    }
+}
+
+fn targets(weights: &[(&str, f64)]) -> HashMap<String, f64> {
+    weights.iter().map(|(s, w)| (s.to_string(), *w)).collect()
+}
+
+#[test]
+fn test_drift_against_targets() {
+    // 6,000 AAPL + 4,000 MSFT + 0 cash against 50/40 with 10% cash
+    let holdings = [holding("AAPL", 100.0, &[(60.0, 80.0)]), holding("MSFT", 200.0, &[(20.0, 150.0)])];
+    let report = RebalanceEngine::default()
+        .drift(&holdings, &targets(&[("AAPL", 0.5), ("MSFT", 0.4)]), 0.0, 0.05)
+        .unwrap();
+    assert!((report.total_value - 10_000.0).abs() < 1e-9);
+    let aapl = report.rows.iter().find(|r| r.symbol == "AAPL").unwrap();
+    assert!((aapl.drift - 0.1).abs() < 1e-12);
+    assert!((aapl.drift_value - 1_000.0).abs() < 1e-9);
+    assert!(aapl.breached);
+    assert!(!report.rows.iter().find(|r| r.symbol == "MSFT").unwrap().breached);
+    assert!((report.target_cash_weight - 0.1).abs() < 1e-12);
+    assert!((report.total_drift - 0.1).abs() < 1e-12);
+    assert!(report.needs_rebalance);
+
+    let over = RebalanceEngine::default().drift(&holdings, &targets(&[("AAPL", 0.7), ("MSFT", 0.4)]), 0.0, 0.05);
+    assert!(over.is_err());
+}
+
+#[test]
+fn test_plan_rounds_to_lots_and_keeps_cash_buffer() {
+    let holdings = [
+        holding("AAPL", 100.0, &[(60.0, 80.0)]),
+        holding("MSFT", 200.0, &[(20.0, 150.0)]),
+        holding("XOM", 30.0, &[(2.0, 35.0)]),
+        holding("JNJ", 150.0, &[]),
+    ];
+    let options = RebalanceOptions {
+        cash_buffer: 0.02,
+        min_trade_value: 100.0,
+        lot_sizes: HashMap::from([("JNJ".to_string(), 10.0)]),
+        ..Default::default()
+    };
+    let targets = targets(&[("AAPL", 0.4), ("MSFT", 0.4), ("JNJ", 0.2)]);
+    let plan = RebalanceEngine::new(options).plan(&holdings, &targets, 500.0).unwrap();
+
+    // Total 10,560, investable 10,348.8
+    assert!((plan.total_value - 10_560.0).abs() < 1e-9);
+    let trade = |symbol: &str| plan.trades.iter().find(|t| t.symbol == symbol);
+    let aapl = trade("AAPL").unwrap();
+    assert_eq!(aapl.side, TradeSide::Sell);
+    assert_eq!(aapl.quantity, 18.0);
+    // XOM has no target but is below the minimum trade value
+    assert!(trade("XOM").is_none());
+    // MSFT wants 0.69 shares more: rounds to nothing
+    assert!(trade("MSFT").is_none());
+    let jnj = trade("JNJ").unwrap();
+    assert_eq!(jnj.side, TradeSide::Buy);
+    assert_eq!(jnj.quantity, 10.0);
+
+    assert!((plan.cash_after - (500.0 + 1_800.0 - 1_500.0)).abs() < 1e-9);
+    assert!(plan.cash_after >= 0.02 * plan.total_value);
+    assert!(plan.trades.iter().all(|t| t.value >= 100.0));
+    assert!(plan.drift_after.total_drift < 0.1);
+}
+
+#[test]
+fn test_purchases_limited_by_available_cash() {
+    let holdings = [holding("AAPL", 100.0, &[(10.0, 100.0)]), holding("MSFT", 50.0, &[])];
+    let options = RebalanceOptions { cash_buffer: 0.1, ..Default::default() };
+    let plan = RebalanceEngine::new(options).plan(&holdings, &targets(&[("AAPL", 0.5), ("MSFT", 0.5)]), 1_000.0).unwrap();
+
+    // Investable 1,800: AAPL sells 1 share, MSFT gets the 200 of spare cash after the buffer
+    let msft = plan.trades.iter().find(|t| t.symbol == "MSFT").unwrap();
+    assert_eq!(msft.quantity, 18.0);
+    assert!((plan.cash_after - 200.0).abs() < 1e-9);
+    assert!(RebalanceEngine::default().plan(&holdings, &targets(&[("GOOG", 0.5)]), 0.0).is_err());
+}
+
+#[test]
+fn test_short_positions_are_not_rebalanced() {
+    let holdings = [holding("AAPL", 100.0, &[(10.0, 90.0)]), holding("TSLA", 200.0, &[(-5.0, 210.0)])];
+    let target = targets(&[("AAPL", 0.5), ("TSLA", 0.3)]);
+    let plan = RebalanceEngine::default().plan(&holdings, &target, 1_000.0).unwrap_err();
+    assert!(plan.to_string().contains("TSLA"));
+    assert!(RebalanceEngine::default().drift(&holdings, &target, 1_000.0, 0.05).is_err());
+}
+
+#[test]
+fn test_lot_selection_minimizes_realized_gains() {
+    let holdings = [holding("AAPL", 100.0, &[(10.0, 50.0), (10.0, 120.0), (10.0, 90.0)])];
+    let target = targets(&[("AAPL", 0.5)]);
+
+    let fifo = RebalanceEngine::default().plan(&holdings, &target, 0.0).unwrap();
+    assert_eq!(fifo.trades[0].quantity, 15.0);
+    assert_eq!(fifo.trades[0].lots.iter().map(|l| l.lot_index).collect::<Vec<_>>(), vec![0, 1]);
+    assert!((fifo.realized_gain - (10.0 * 50.0 - 5.0 * 20.0)).abs() < 1e-9);
+
+    let options = RebalanceOptions { lot_selection: LotSelection::MinimizeGains, ..Default::default() };
+    let tax_aware = RebalanceEngine::new(options).plan(&holdings, &target, 0.0).unwrap();
+    assert_eq!(tax_aware.trades[0].lots.iter().map(|l| l.lot_index).collect::<Vec<_>>(), vec![1, 2]);
+    // Posted with these lot IDs, the ledger relieves the lots the plan chose
+    assert_eq!(tax_aware.trades[0].lot_ids(), vec!["AAPL-1", "AAPL-2"]);
+    assert!((tax_aware.realized_gain - (-10.0 * 20.0 + 5.0 * 10.0)).abs() < 1e-9);
+    assert!(tax_aware.realized_gain < fifo.realized_gain);
+
+    let model = normalize_weights(&HashMap::from([("aapl".to_string(), 60.0), ("MSFT".to_string(), 40.0)]), 1.0).unwrap();
+    assert!((model["AAPL"] - 0.6).abs() < 1e-12);
+    assert!(normalize_weights(&HashMap::new(), 1.0).is_err());
+}
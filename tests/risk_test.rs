@@ -0,0 +1,399 @@
+use chrono::{Duration, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashMap};
+use valuation_service::{
+    aligned_daily_returns, backtest_var, is_positive_semi_definite, make_valuator, nearest_correlation_matrix, Bond,
+    CovarianceEstimator, CovarianceMethod, DailyCloses, ExerciseStyle, FinancialOption, HistoricalWeighting, Instrument, MarketContext, OptionType, PaymentFrequency, Portfolio,
+    PortfolioValuationService, PricingModelKind, RiskEngine, Stock, StressScenario, StressType, TrafficLight, VarBacktestObservation,
+};
+
+fn context(spot: Option<f64>) -> MarketContext {
+    MarketContext {
+        risk_free_rate: 0.04,
+        dividend_yield: None,
+        volatility: Some(0.2),
+        spot_price: spot,
+        forward_curve: None,
+        yield_curve: None,
+        timestamp: Utc::now(),
+    }
+}
+
+#[test]
+fn test_stress_test_reprices_bonds_and_options() {
+    let bond = Bond::new(
+        "US912828XG55".to_string(),
+        "USD".to_string(),
+        1000.0,
+        0.04,
+        Utc::now() + Duration::days(365 * 10),
+        Utc::now() - Duration::days(30),
+        PaymentFrequency::SemiAnnual,
+    );
+    let option = FinancialOption::new(
+        "AAPL".to_string(),
+        "USD".to_string(),
+        OptionType::Call,
+        100.0,
+        Utc::now() + Duration::days(90),
+        1.0,
+        ExerciseStyle::European,
+    );
+    let mut portfolio = Portfolio::new("stress".to_string(), "USD".to_string());
+    portfolio.add_position(bond.id.clone(), 10_000.0, None);
+    portfolio.add_position(option.id.clone(), 100.0, None);
+
+    let mut instruments: HashMap<String, Box<dyn Instrument + Send + Sync>> = HashMap::new();
+    instruments.insert(bond.id.clone(), Box::new(bond.clone()));
+    instruments.insert(option.id.clone(), Box::new(option.clone()));
+    let contexts = HashMap::from([
+        (bond.isin.clone(), context(None)),
+        ("AAPL".to_string(), context(Some(100.0))),
+    ]);
+
+    let scenarios = vec![
+        StressScenario { name: "rates +100bp".to_string(), scenario_type: StressType::RateShock, shock_magnitude: 0.01 },
+        StressScenario { name: "vol +50%".to_string(), scenario_type: StressType::VolatilityShock, shock_magnitude: 0.5 },
+    ];
+    let valuator = make_valuator(PricingModelKind::BlackScholes);
+    let results = RiskEngine::default()
+        .stress_test(&portfolio, &instruments, valuator.as_ref(), &contexts, &scenarios)
+        .unwrap();
+
+    let pnl_for = |scenario: usize, id: &str| {
+        results[scenario].positions.iter().find(|p| p.instrument_id == id).unwrap().pnl
+    };
+
+    // A 10y bond near par loses roughly duration (~8.2) x 1% of its value
+    let bond_pnl = pnl_for(0, &bond.id);
+    let bond_value = results[0].positions.iter().find(|p| p.instrument_id == bond.id).unwrap().base_value;
+    let loss_pct = -bond_pnl / bond_value;
+    assert!(loss_pct > 0.07 && loss_pct < 0.09, "bond loss {}", loss_pct);
+
+    // Vol shock leaves the bond untouched and lifts the long call
+    assert!(pnl_for(1, &bond.id).abs() < 1e-9);
+    assert!(pnl_for(1, &option.id) > 0.0);
+    let total: f64 = results[1].positions.iter().map(|p| p.pnl).sum();
+    assert!((total - results[1].pnl).abs() < 1e-6);
+}
+
+type InstrumentMap = HashMap<String, Box<dyn Instrument + Send + Sync>>;
+
+fn stock_book(shares: f64) -> (Portfolio, InstrumentMap, HashMap<String, MarketContext>) {
+    let mut stock = Stock::new("AAPL".to_string(), "USD".to_string(), 1.0);
+    stock.id = "AAPL".to_string();
+    let mut portfolio = Portfolio::new("hist".to_string(), "USD".to_string());
+    portfolio.add_position(stock.id.clone(), shares, None);
+    let mut instruments: InstrumentMap = HashMap::new();
+    instruments.insert(stock.id.clone(), Box::new(stock));
+    let contexts = HashMap::from([("AAPL".to_string(), context(Some(100.0)))]);
+    (portfolio, instruments, contexts)
+}
+
+#[test]
+fn test_historical_var_equal_and_age_weighted() {
+    // 100 daily closes: returns cycle through -5%..+4%
+    let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let mut series = BTreeMap::new();
+    let mut price = 100.0;
+    series.insert(start, price);
+    for day in 1..=100 {
+        price *= 1.0 + ((day % 10) as f64 - 5.0) / 100.0;
+        series.insert(start + Duration::days(day), price);
+    }
+    let closes: DailyCloses = HashMap::from([("AAPL".to_string(), series)]);
+    let history = aligned_daily_returns(&closes, 100).unwrap();
+    assert_eq!(history.len(), 100);
+
+    let (portfolio, instruments, contexts) = stock_book(10.0);
+    let valuator = make_valuator(PricingModelKind::BlackScholes);
+    let engine = RiskEngine::new(0.95, 1, 0);
+
+    let equal = engine
+        .historical_var(&portfolio, &instruments, valuator.as_ref(), &contexts, &history, HistoricalWeighting::Equal)
+        .unwrap();
+    // Worst 5% of days are the ten -5% days: a $50 loss on a $1,000 position
+    assert!((equal.var - 50.0).abs() < 1e-6, "var {}", equal.var);
+    assert!((equal.expected_shortfall - 50.0).abs() < 1e-6);
+
+    let brw = engine
+        .historical_var(
+            &portfolio,
+            &instruments,
+            valuator.as_ref(),
+            &contexts,
+            &history,
+            HistoricalWeighting::AgeWeighted { lambda: 0.97 },
+        )
+        .unwrap();
+    let total_weight: f64 = brw.scenario_pnl.iter().map(|s| s.weight).sum();
+    assert!((total_weight - 1.0).abs() < 1e-9);
+    assert!(brw.scenario_pnl[99].weight > brw.scenario_pnl[0].weight);
+    assert!(brw.var > 0.0 && brw.expected_shortfall >= brw.var);
+
+    let hull_white = engine.historical_var(
+        &portfolio,
+        &instruments,
+        valuator.as_ref(),
+        &contexts,
+        &history,
+        HistoricalWeighting::VolatilityScaled { lambda: 1.5 },
+    );
+    assert!(hull_white.is_err());
+
+    // A P&L that could not be computed is an error rather than a panic while sorting
+    let with_nan = [-0.02, f64::NAN, 0.01];
+    assert!(engine.calculate_var(&with_nan).is_err());
+    assert!(engine.calculate_expected_shortfall(&with_nan).is_err());
+    assert!(engine.calculate_var(&[-0.02, 0.01, f64::NEG_INFINITY]).is_err());
+}
+
+#[test]
+fn test_parametric_var_delta_normal_and_delta_gamma() {
+    let option = FinancialOption::new(
+        "AAPL".to_string(),
+        "USD".to_string(),
+        OptionType::Call,
+        100.0,
+        Utc::now() + Duration::days(30),
+        1.0,
+        ExerciseStyle::European,
+    );
+    let (mut portfolio, mut instruments, contexts) = stock_book(100.0);
+    portfolio.add_position(option.id.clone(), 200.0, None);
+    instruments.insert(option.id.clone(), Box::new(option));
+
+    let engine = RiskEngine::new(0.99, 1, 0);
+    let valuator = make_valuator(PricingModelKind::BlackScholes);
+    let symbols = vec!["AAPL".to_string()];
+    let exposures = engine
+        .underlying_exposures(&portfolio, &instruments, valuator.as_ref(), &contexts, &symbols)
+        .unwrap();
+    // 100 shares plus 200 ATM calls (delta ~0.53) on a $100 stock
+    assert!(exposures[0].dollar_delta > 100.0 * 100.0 + 200.0 * 0.5 * 100.0);
+    assert!(exposures[0].dollar_gamma > 0.0);
+
+    let daily_vol: f64 = 0.02;
+    let covariance = nalgebra::DMatrix::from_element(1, 1, daily_vol * daily_vol);
+    let normal = engine.delta_normal_var(&exposures, &covariance).unwrap();
+    let expected = 2.326_347_874 * exposures[0].dollar_delta * daily_vol;
+    assert!((normal.var - expected).abs() / expected < 1e-6, "var {} vs {}", normal.var, expected);
+
+    // Long gamma skews P&L to the right, so the loss quantile is below delta-normal
+    let gamma = engine.delta_gamma_var(&exposures, &covariance).unwrap();
+    assert!(gamma.skewness > 0.0);
+    assert!(gamma.var < normal.var && gamma.var > 0.0);
+}
+
+#[test]
+fn test_monte_carlo_var_diversifies_across_assets() {
+    let mut portfolio = Portfolio::new("mc".to_string(), "USD".to_string());
+    let mut instruments: InstrumentMap = HashMap::new();
+    let mut contexts = HashMap::new();
+    for symbol in ["AAA", "BBB"] {
+        let mut stock = Stock::new(symbol.to_string(), "USD".to_string(), 1.0);
+        stock.id = symbol.to_string();
+        portfolio.add_position(stock.id.clone(), 100.0, None);
+        instruments.insert(stock.id.clone(), Box::new(stock));
+        contexts.insert(symbol.to_string(), context(Some(100.0)));
+    }
+    let symbols = vec!["AAA".to_string(), "BBB".to_string()];
+    let engine = RiskEngine::new(0.99, 1, 20_000);
+    let valuator = make_valuator(PricingModelKind::BlackScholes);
+    let var_for = |rho: f64| {
+        let v = 0.02_f64 * 0.02;
+        let covariance = nalgebra::DMatrix::from_row_slice(2, 2, &[v, rho * v, rho * v, v]);
+        engine
+            .monte_carlo_var(&portfolio, &instruments, valuator.as_ref(), &contexts, &symbols, &covariance)
+            .unwrap()
+    };
+
+    let independent = var_for(0.0);
+    let perfect = var_for(1.0);
+    assert_eq!(independent.pnl_distribution.len(), 20_000);
+    assert!(independent.expected_shortfall >= independent.var);
+    // $20,000 book, 2% daily vol: ~$931 undiversified vs ~$658 with zero correlation
+    assert!((perfect.var - 931.0).abs() < 60.0, "perfect {}", perfect.var);
+    assert!((independent.var - 658.0).abs() < 60.0, "independent {}", independent.var);
+}
+
+#[test]
+fn test_factorize_covariance_handles_non_positive_definite() {
+    let engine = RiskEngine::default();
+    // Perfectly correlated assets: singular, so Cholesky fails
+    let covariance = nalgebra::DMatrix::from_row_slice(2, 2, &[1.0, 1.0, 1.0, 1.0]);
+    let factor = engine.factorize_covariance(&covariance).unwrap();
+    let rebuilt = &factor * factor.transpose();
+    assert!((rebuilt - covariance).abs().max() < 1e-9);
+}
+
+#[test]
+fn test_covariance_estimators() {
+    let a = [0.01, -0.02, 0.015, 0.003, -0.007, 0.012];
+    let b = [0.008, -0.015, 0.01, 0.001, -0.004, 0.009];
+    let returns = vec![a.to_vec(), b.to_vec()];
+
+    let sample = CovarianceEstimator::new(CovarianceMethod::Sample, false).estimate(&returns).unwrap();
+    let mean = a.iter().sum::<f64>() / 6.0;
+    let variance = a.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 5.0;
+    assert!((sample[(0, 0)] - variance).abs() < 1e-12);
+
+    // EWMA puts the most weight on recent observations
+    let ewma = CovarianceEstimator::new(CovarianceMethod::Ewma { lambda: 0.94 }, false).estimate(&returns).unwrap();
+    let weights: Vec<f64> = (0..6).map(|t| 0.94f64.powi(5 - t)).collect();
+    let expected = a.iter().zip(&weights).map(|(r, w)| w * r * r).sum::<f64>() / weights.iter().sum::<f64>();
+    assert!((ewma[(0, 0)] - expected).abs() < 1e-12);
+
+    // Shrinkage pulls the off-diagonal towards zero but keeps the average variance
+    let shrunk = CovarianceEstimator::new(CovarianceMethod::LedoitWolf, false).estimate(&returns).unwrap();
+    assert!(shrunk[(0, 1)].abs() < sample[(0, 1)].abs());
+    assert!(shrunk[(0, 1)] > 0.0);
+    assert!((shrunk.trace() - sample.trace() * 5.0 / 6.0).abs() < 1e-12);
+
+    // A missing observation only drops that date for the affected pairs
+    let mut gappy = returns.clone();
+    gappy[1][2] = f64::NAN;
+    let pairwise = CovarianceEstimator::new(CovarianceMethod::Sample, false).estimate(&gappy).unwrap();
+    assert!((pairwise[(0, 0)] - sample[(0, 0)]).abs() < 1e-15);
+    assert!(pairwise[(1, 1)].is_finite() && pairwise[(0, 1)].is_finite());
+}
+
+#[test]
+fn test_correlation_repair_makes_pairwise_estimates_usable() {
+    // Pairwise-complete data where no consistent correlation matrix exists
+    let nan = f64::NAN;
+    let returns = vec![
+        vec![0.01, -0.01, 0.02, -0.02, nan, nan, 0.01, -0.01],
+        vec![0.01, -0.01, 0.02, -0.02, 0.01, -0.01, nan, nan],
+        vec![nan, nan, nan, nan, -0.01, 0.01, 0.01, -0.01],
+    ];
+    let raw = CovarianceEstimator::new(CovarianceMethod::Sample, false).estimate_correlation(&returns).unwrap();
+    assert!(!is_positive_semi_definite(&raw));
+
+    let engine = RiskEngine::default();
+    let correlation = engine.calculate_correlation_matrix(&returns).unwrap();
+    assert!(correlation.clone().cholesky().is_some());
+    for i in 0..3 {
+        assert!((correlation[(i, i)] - 1.0).abs() < 1e-12);
+    }
+    assert!((nearest_correlation_matrix(&raw) - &correlation).abs().max() < 1e-9);
+
+    let var = engine.calculate_portfolio_var(&[0.4, 0.3, 0.3], &[0.2, 0.25, 0.3], &raw, 100_000.0).unwrap();
+    assert!(var.is_finite() && var > 0.0);
+}
+
+fn var_history(exception_days: &[usize]) -> Vec<VarBacktestObservation> {
+    let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    (0..250)
+        .map(|day| VarBacktestObservation {
+            date: start + Duration::days(day as i64),
+            var: 1_000.0,
+            pnl: if exception_days.contains(&day) { -1_500.0 } else { 200.0 },
+        })
+        .collect()
+}
+
+#[test]
+fn test_var_backtest_coverage_and_traffic_light() {
+    let good = backtest_var(&var_history(&[20, 90, 180]), 0.99, 0.05).unwrap();
+    assert_eq!(good.exceptions, 3);
+    assert!((good.expected_exceptions - 2.5).abs() < 1e-12);
+    assert_eq!(good.traffic_light, TrafficLight::Green);
+    assert!(!good.kupiec.rejected && !good.independence.rejected && !good.conditional_coverage.rejected);
+
+    // Six exceptions in a row: yellow zone and clearly clustered
+    let clustered = backtest_var(&var_history(&[100, 101, 102, 103, 104, 105]), 0.99, 0.05).unwrap();
+    assert_eq!(clustered.traffic_light, TrafficLight::Yellow);
+    assert!(clustered.independence.rejected);
+    assert!(clustered.conditional_coverage.rejected);
+    assert_eq!(clustered.exception_dates.len(), 6);
+
+    let red = backtest_var(&var_history(&(0..25).map(|d| d * 10).collect::<Vec<_>>()), 0.99, 0.05).unwrap();
+    assert_eq!(red.traffic_light, TrafficLight::Red);
+    assert!(red.kupiec.rejected);
+    // Kupiec LR for 25 exceptions in 250 days at 99%
+    let (n, x, p, rate) = (250.0, 25.0, 0.01f64, 0.1f64);
+    let expected = -2.0 * ((n - x) * (1.0 - p).ln() + x * p.ln() - (n - x) * (1.0 - rate).ln() - x * rate.ln());
+    assert!((red.kupiec.statistic - expected).abs() < 1e-9);
+}
+
+#[tokio::test]
+async fn test_portfolio_risk_decomposition_sums_to_total() {
+    // Two partially correlated stocks with roughly 1% and 2% daily vol
+    let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let mut closes: DailyCloses = HashMap::new();
+    for (symbol, moves) in [("AAPL", [0.01, -0.01]), ("MSFT", [0.02, -0.02])] {
+        let mut price = 100.0;
+        let series = closes.entry(symbol.to_string()).or_default();
+        series.insert(start, price);
+        for day in 1..=40 {
+            // MSFT skips every fourth move to decorrelate partially
+            let r = if symbol == "MSFT" && day % 4 == 0 { -moves[day as usize % 2] } else { moves[day as usize % 2] };
+            price *= 1.0 + r;
+            series.insert(start + Duration::days(day), price);
+        }
+    }
+    let history = aligned_daily_returns(&closes, 40).unwrap();
+
+    let mut portfolio = Portfolio::new("risk".to_string(), "USD".to_string());
+    let mut instruments: InstrumentMap = HashMap::new();
+    let mut contexts = HashMap::new();
+    for (symbol, shares) in [("AAPL", 100.0), ("MSFT", 50.0)] {
+        let mut stock = Stock::new(symbol.to_string(), "USD".to_string(), 1.0);
+        stock.id = symbol.to_string();
+        portfolio.add_position(stock.id.clone(), shares, None);
+        instruments.insert(stock.id.clone(), Box::new(stock));
+        contexts.insert(symbol.to_string(), context(Some(100.0)));
+    }
+
+    let service = PortfolioValuationService::new(RiskEngine::new(0.99, 1, 0));
+    let valuator = make_valuator(PricingModelKind::BlackScholes);
+    let valuation = service
+        .value_portfolio_with_risk(&portfolio, &instruments, valuator.as_ref(), &contexts, &history)
+        .await
+        .unwrap();
+    let risk = valuation.risk_decomposition.unwrap();
+
+    let component_sum: f64 = risk.positions.iter().map(|p| p.component_var).sum();
+    assert!((component_sum - risk.var).abs() < 1e-9);
+    let pct_sum: f64 = risk.positions.iter().map(|p| p.contribution_percentage).sum();
+    assert!((pct_sum - 100.0).abs() < 1e-9);
+
+    // Same number as delta-normal VaR on the underlying exposures
+    let engine = RiskEngine::new(0.99, 1, 0);
+    let exposures = engine
+        .underlying_exposures(&portfolio, &instruments, valuator.as_ref(), &contexts, &history.symbols)
+        .unwrap();
+    let covariance = engine.calculate_covariance_matrix(&history.returns).unwrap();
+    let delta_normal = engine.delta_normal_var(&exposures, &covariance).unwrap();
+    assert!((risk.var - delta_normal.var).abs() < 1e-9);
+    assert_eq!(valuation.risk_metrics.unwrap().var_1d, Some(risk.var));
+
+    let z = 2.3263478740408408;
+    for (i, position) in risk.positions.iter().enumerate() {
+        // Diversification: incremental <= component < standalone VaR
+        let standalone = z * position.exposure * covariance[(i, i)].sqrt();
+        assert!(position.incremental_var > 0.0 && position.incremental_var <= position.component_var + 1e-9);
+        assert!(position.component_var < standalone);
+        assert!((position.marginal_var * position.exposure - position.component_var).abs() < 1e-9);
+    }
+
+    // The legacy weight/volatility API allocates the same way
+    let weights = [0.5, 0.5];
+    let correlation = engine.calculate_correlation_matrix(&history.returns).unwrap();
+    let total = engine.calculate_portfolio_var(&weights, &[0.2, 0.3], &correlation, 1_000.0).unwrap();
+    let components = engine.calculate_component_var(&weights, &[0.2, 0.3], &correlation, 1_000.0).unwrap();
+    // Reported as a positive loss, like the other VaR measures
+    assert!(total > 0.0);
+    assert!(components.iter().all(|c| *c > 0.0));
+    assert!((components.iter().sum::<f64>() - total).abs() < 1e-9);
+}
+
+#[test]
+fn test_beta_against_benchmark() {
+    let engine = RiskEngine::default();
+    let benchmark = [0.01, -0.02, 0.015, 0.005, -0.01];
+    let levered: Vec<f64> = benchmark.iter().map(|r| 1.5 * r + 0.001).collect();
+    assert!((engine.calculate_beta(&levered, &benchmark).unwrap() - 1.5).abs() < 1e-12);
+    assert!(engine.calculate_beta(&levered, &[0.0; 5]).is_err());
+    assert!(engine.calculate_beta(&levered[..3], &benchmark).is_err());
+}
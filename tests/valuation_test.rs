@@ -0,0 +1,238 @@
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use valuation_service::{
+    make_valuator, value_instruments, BlackScholesModel, Bond, ExerciseStyle, FinancialOption, Instrument,
+    MarketContext, MarketShift, OptionType, PaymentFrequency, Portfolio, PortfolioValuationService,
+    PricingModelKind, Scenario, ShiftMode, Stock, Valuator,
+};
+
+fn context(spot: f64) -> MarketContext {
+    MarketContext {
+        risk_free_rate: 0.05,
+        dividend_yield: None,
+        volatility: Some(0.2),
+        spot_price: Some(spot),
+        forward_curve: None,
+        yield_curve: None,
+        timestamp: Utc::now(),
+    }
+}
+
+#[test]
+fn test_batch_valuation_totals_and_greeks() {
+    let stock = Stock::new("AAPL".to_string(), "USD".to_string(), 10.0);
+    let option = FinancialOption::new(
+        "AAPL".to_string(),
+        "USD".to_string(),
+        OptionType::Call,
+        100.0,
+        Utc::now() + Duration::days(365),
+        2.0,
+        ExerciseStyle::European,
+    );
+    let items: Vec<(&dyn Instrument, MarketContext)> = vec![(&stock, context(100.0)), (&option, context(100.0))];
+
+    let response = value_instruments(&BlackScholesModel::new(), &items, true, true, "USD");
+
+    assert!(response.errors.is_empty());
+    assert_eq!(response.results.len(), 2);
+    // 1y ATM call with r=5%, vol=20% is worth ~10.45 per unit
+    let option_value = response.results[1].value;
+    assert!((option_value - 2.0 * 10.45).abs() < 0.1, "option value {}", option_value);
+    assert!((response.total_value - (1000.0 + option_value)).abs() < 1e-9);
+
+    // 10 shares (delta 1 each) plus 2 calls at ~0.637
+    let delta = response.total_greeks.unwrap().delta.unwrap();
+    assert!((delta - (10.0 + 2.0 * 0.637)).abs() < 0.01, "delta {}", delta);
+    assert!(response.results[0].risk_metrics.as_ref().unwrap().var_1d.unwrap() > 0.0);
+
+    // Monte Carlo has no option Greeks, which requests asking for them are told up front
+    assert!(PricingModelKind::BlackScholes.has_option_greeks());
+    assert!(!PricingModelKind::MonteCarlo.has_option_greeks());
+    let mc = make_valuator(PricingModelKind::MonteCarlo);
+    assert_eq!(mc.calculate_greeks(&option, &context(100.0)).unwrap().delta, None);
+    assert_eq!(mc.calculate_greeks(&stock, &context(100.0)).unwrap().delta, Some(1.0));
+}
+
+#[test]
+fn test_options_are_valued_as_of_the_market_context_timestamp() {
+    let expiry = Utc::now() + Duration::days(30);
+    let option = FinancialOption::new(
+        "AAPL".to_string(),
+        "USD".to_string(),
+        OptionType::Call,
+        90.0,
+        expiry,
+        1.0,
+        ExerciseStyle::European,
+    );
+    let model = BlackScholesModel::new();
+
+    // Valued a year before expiry, the call carries a year of time value
+    let mut early = context(100.0);
+    early.timestamp = expiry - Duration::days(365);
+    let early_result = model.value(&option, &early).unwrap();
+    assert_eq!(early_result.timestamp, early.timestamp);
+    assert!(early_result.value > 10.0 + 4.0, "value {}", early_result.value);
+
+    // Valued after expiry, it is worth its intrinsic value whatever the wall clock says
+    let mut expired = context(100.0);
+    expired.timestamp = expiry + Duration::days(1);
+    assert!((model.value(&option, &expired).unwrap().value - 10.0).abs() < 1e-12);
+    assert_eq!(model.calculate_greeks(&option, &expired).unwrap().delta, Some(0.0));
+}
+
+#[test]
+fn test_batch_valuation_reports_unsupported_instruments() {
+    let bond = Bond::new(
+        "US0000000000".to_string(),
+        "USD".to_string(),
+        1000.0,
+        0.04,
+        Utc::now() + Duration::days(365 * 5),
+        Utc::now(),
+        PaymentFrequency::SemiAnnual,
+    );
+    let stock = Stock::new("MSFT".to_string(), "USD".to_string(), 1.0);
+    let items: Vec<(&dyn Instrument, MarketContext)> = vec![(&bond, context(100.0)), (&stock, context(400.0))];
+
+    let response = value_instruments(&BlackScholesModel::new(), &items, false, false, "USD");
+
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(response.errors[0].instrument_id, bond.id);
+    assert_eq!(response.total_value, 400.0);
+    assert!(response.total_greeks.is_none());
+}
+
+#[tokio::test]
+async fn test_scenario_revaluation_pnl() {
+    let mut stock = Stock::new("AAPL".to_string(), "USD".to_string(), 1.0);
+    stock.id = "AAPL".to_string();
+    let option = FinancialOption::new(
+        "AAPL".to_string(),
+        "USD".to_string(),
+        OptionType::Call,
+        100.0,
+        Utc::now() + Duration::days(180),
+        1.0,
+        ExerciseStyle::European,
+    );
+    let mut portfolio = Portfolio::new("test".to_string(), "USD".to_string());
+    portfolio.add_position(stock.id.clone(), 10.0, Some(90.0));
+    portfolio.add_position(option.id.clone(), 5.0, None);
+
+    let mut instruments: HashMap<String, Box<dyn Instrument + Send + Sync>> = HashMap::new();
+    instruments.insert(option.id.clone(), Box::new(option));
+    instruments.insert(stock.id.clone(), Box::new(stock));
+    let contexts = HashMap::from([("AAPL".to_string(), context(100.0))]);
+
+    let scenarios = vec![
+        Scenario {
+            name: "AAPL -10%, vol +5".to_string(),
+            shifts: vec![
+                MarketShift::Spot { symbol: Some("AAPL".to_string()), mode: ShiftMode::Relative, amount: -0.10 },
+                MarketShift::Volatility { symbol: None, mode: ShiftMode::Absolute, amount: 0.05 },
+            ],
+        },
+        Scenario { name: "one month".to_string(), shifts: vec![MarketShift::TimeRoll { days: 30 }] },
+    ];
+
+    let results = PortfolioValuationService::default()
+        .value_scenarios(&portfolio, &instruments, &BlackScholesModel::new(), &contexts, &scenarios)
+        .await
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    let stock_pnl = results[0].positions.iter().find(|p| p.instrument_id == "AAPL").unwrap().pnl;
+    assert!((stock_pnl + 100.0).abs() < 1e-9);
+    let total: f64 = results[0].positions.iter().map(|p| p.pnl).sum();
+    assert!((total - results[0].pnl).abs() < 1e-9);
+    // Time decay alone costs the long call
+    assert!(results[1].pnl < 0.0);
+}
+
+#[tokio::test]
+async fn test_scenario_rejects_missing_tenor_and_prices_zero_volatility() {
+    let option = FinancialOption::new(
+        "AAPL".to_string(),
+        "USD".to_string(),
+        OptionType::Call,
+        90.0,
+        Utc::now() + Duration::days(365),
+        1.0,
+        ExerciseStyle::European,
+    );
+    let mut portfolio = Portfolio::new("test".to_string(), "USD".to_string());
+    portfolio.add_position(option.id.clone(), 1.0, None);
+    let mut instruments: HashMap<String, Box<dyn Instrument + Send + Sync>> = HashMap::new();
+    instruments.insert(option.id.clone(), Box::new(option));
+    let mut aapl = context(100.0);
+    aapl.yield_curve = Some(HashMap::from([("1Y".to_string(), 0.05), ("5Y".to_string(), 0.05)]));
+    let contexts = HashMap::from([("AAPL".to_string(), aapl)]);
+    let service = PortfolioValuationService::default();
+
+    let missing = Scenario {
+        name: "2Y +100bp".to_string(),
+        shifts: vec![MarketShift::Rate { tenor: Some("2Y".to_string()), amount: 0.01 }],
+    };
+    let err = service
+        .value_scenarios(&portfolio, &instruments, &BlackScholesModel::new(), &contexts, &[missing])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("2Y"), "{}", err);
+
+    // Without any curve the shift has nothing to move, which is an error too rather than no P&L
+    let flat = HashMap::from([("AAPL".to_string(), context(100.0))]);
+    let one_year = Scenario {
+        name: "1Y +100bp".to_string(),
+        shifts: vec![MarketShift::Rate { tenor: Some("1Y".to_string()), amount: 0.01 }],
+    };
+    let err = service
+        .value_scenarios(&portfolio, &instruments, &BlackScholesModel::new(), &flat, &[one_year])
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("No yield curve for AAPL"), "{}", err);
+
+    // Volatility floored at zero: the call is worth its discounted forward intrinsic value
+    let crush = Scenario {
+        name: "vol crush".to_string(),
+        shifts: vec![MarketShift::Volatility { symbol: None, mode: ShiftMode::Absolute, amount: -0.5 }],
+    };
+    let results = service
+        .value_scenarios(&portfolio, &instruments, &BlackScholesModel::new(), &contexts, &[crush])
+        .await
+        .unwrap();
+    let crushed = results[0].positions[0].scenario_value;
+    let intrinsic = 100.0 - 90.0 * (-0.05_f64).exp();
+    assert!((crushed - intrinsic).abs() < 0.01, "value {}", crushed);
+}
+
+#[tokio::test]
+async fn test_best_effort_valuation_reports_failed_positions() {
+    let mut stock = Stock::new("AAPL".to_string(), "USD".to_string(), 1.0);
+    stock.id = "AAPL".to_string();
+    let mut unpriced = Stock::new("MSFT".to_string(), "USD".to_string(), 1.0);
+    unpriced.id = "MSFT".to_string();
+    let mut portfolio = Portfolio::new("test".to_string(), "USD".to_string());
+    portfolio.add_position(stock.id.clone(), 10.0, Some(90.0));
+    portfolio.add_position(unpriced.id.clone(), 5.0, Some(300.0));
+    portfolio.add_position("UNKNOWN".to_string(), 1.0, None);
+
+    let mut instruments: HashMap<String, Box<dyn Instrument + Send + Sync>> = HashMap::new();
+    instruments.insert(stock.id.clone(), Box::new(stock));
+    instruments.insert(unpriced.id.clone(), Box::new(unpriced));
+    let contexts = HashMap::from([("AAPL".to_string(), context(100.0))]);
+
+    let valuation = PortfolioValuationService::default()
+        .value_portfolio_best_effort(&portfolio, &instruments, &BlackScholesModel::new(), &contexts, None)
+        .await;
+
+    assert_eq!(valuation.positions.len(), 1);
+    assert!((valuation.total_value - 1000.0).abs() < 1e-9);
+    assert!((valuation.positions[0].weight - 100.0).abs() < 1e-9);
+    assert!((valuation.positions[0].pnl.unwrap() - 100.0).abs() < 1e-9);
+    let mut failed: Vec<&str> = valuation.errors.iter().map(|e| e.instrument_id.as_str()).collect();
+    failed.sort();
+    assert_eq!(failed, vec!["MSFT", "UNKNOWN"]);
+    assert!(valuation.risk_metrics.is_none());
+}